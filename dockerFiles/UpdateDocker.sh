@@ -3,10 +3,22 @@
 # docker build -t 'virtual_machine' - < Dockerfile
 
 echo "Creating Docker Image - Python"
-docker build -t 'virtual_machine_python' - < DockerFilePython
+docker build -t 'python_virtual_machine' - < DockerFilePython
 
 echo "Creating Docker Image - Node"
-docker build -t 'virtual_machine_node' - < DockerFileNode
+docker build -t 'node_virtual_machine' - < DockerFileNode
+
+echo "Creating Docker Image - C++"
+docker build -t 'cpp_virtual_machine' - < DockerFileCpp
+
+echo "Creating Docker Image - Java"
+docker build -t 'java_virtual_machine' - < DockerFileJava
+
+echo "Creating Docker Image - Go"
+docker build -t 'go_virtual_machine' - < DockerFileGo
+
+echo "Creating Docker Image - Rust"
+docker build -t 'rust_virtual_machine' - < DockerFileRust
 
 echo "Retrieving Installed Docker Images"
 docker images
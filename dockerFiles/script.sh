@@ -1,5 +1,10 @@
 #!/bin/bash
 
+# The details of the compiler (e.g the additional arguments) are intentionally split into separate
+# arguments, pathname expansion is disabled so that the split arguments are never expanded into the
+# names of files. Every other argument is only ever expanded quoted.
+set -f
+
 compiler=$1
 sourceFile=$2
 stdInFile=$3
@@ -7,26 +12,122 @@ output=$4
 additionalArguments=$5
 standard_out=$6
 standard_error_out=$7
+stage=$8
+usageFile=$9
+statusFile=${10}
+
+# Any remaining arguments are given to the executed code, these are only ever expanded quoted so
+# that each argument is passed unchanged rather than being split or interpreted by the shell.
+shift 10
+
+# When compiling and running in separate containers the run stage appends to the output written by
+# the compile stage, otherwise the outputs are written from the start.
+if [ "$stage" = "run" ]; then
+  exec 1>>"${standard_out}"
+else
+  exec 1>"${standard_out}"
+fi
+
+# When both outputs are the same file the standard error output is merged into the already open
+# standard output, so that the outputs are interleaved rather than overwriting each other.
+if [ "${standard_error_out}" = "${standard_out}" ]; then
+  exec 2>&1
+elif [ "$stage" = "run" ]; then
+  exec 2>>"${standard_error_out}"
+else
+  exec 2>"${standard_error_out}"
+fi
+
+# The executed code (but not the compiler) is wrapped with the time binary, which reports if the
+# code was terminated by a signal (the shell reports a signal the same as a exit code above 128).
+# When measuring resources the time binary also writes the resource usage of the code to the usage
+# file, otherwise its output is written to the status file, which is overwritten once the signal is
+# read. Images without the time binary run the code directly, leaving the signal and usage unknown.
+timeFile=${usageFile:-${statusFile}}
 
-exec 1>"${standard_out}"
-exec 2>"${standard_error_out}"
+measure() {
+  if [ -x /usr/bin/time ]; then
+    /usr/bin/time -v -o "$timeFile" "$@"
+  else
+    signal="?"
+    "$@"
+  fi
+}
 
 START=$(date +%s.%2N)
 
 if [ "$output" = "" ]; then
-  $compiler "$sourceFile" - <"${stdInFile}"
+  measure $compiler $additionalArguments "$sourceFile" "$@" - <"${stdInFile}"
+  exitCode=$?
+elif [ "$stage" = "run" ]; then
+  measure $output "$@" - <"${stdInFile}"
+  exitCode=$?
 else
-  $compiler "$sourceFile" "$additionalArguments"
+  $compiler $additionalArguments "$sourceFile"
+  exitCode=$?
 
-  if [ $? -eq 0 ]; then
-    $output - <"${stdInFile}"
-  else
-    echo "Compilation Failed"
+  # A failed compile is written to the status file rather than the output, so that the output of
+  # the compiler can never be mistaken for the status.
+  if [ $exitCode -ne 0 ]; then
+    status="compile"
+  elif [ "$stage" != "compile" ]; then
+    measure $output "$@" - <"${stdInFile}"
+    exitCode=$?
   fi
 fi
 
+# The compile stage is followed by the run stage, which writes the end of the output. The status is
+# only written by the compile stage when the compiling failed, since the run stage is not executed.
+if [ "$stage" = "compile" ]; then
+  if [ "$status" = "compile" ]; then
+    echo "$status" - >"${statusFile}"
+  fi
+
+  exit $exitCode
+fi
 
 END=$(date +%s.%2N)
+
+if [ "$signal" != "?" ] && [ -f "$timeFile" ]; then
+  while read -r line; do
+    case "$line" in
+      "Command terminated by signal "*) signal=${line##* } ;;
+    esac
+  done <"$timeFile"
+fi
 runtime=$(echo "$END - $START" | bc)
 
-echo "*-COMPILE::EOF-*" "$runtime"
+# The peak memory usage (in bytes), the cpu time (in nanoseconds) of the container and the number of
+# times a process could not be created due to the process limit are read from the cgroup of the
+# container, supporting both cgroup v2 and v1. A value that is not available is written as a dash.
+if [ -f /sys/fs/cgroup/memory.peak ]; then
+  peakMemory=$(cat /sys/fs/cgroup/memory.peak)
+elif [ -f /sys/fs/cgroup/memory/memory.max_usage_in_bytes ]; then
+  peakMemory=$(cat /sys/fs/cgroup/memory/memory.max_usage_in_bytes)
+fi
+
+if [ -f /sys/fs/cgroup/cpu.stat ]; then
+  while read -r key value; do
+    if [ "$key" = "usage_usec" ]; then
+      cpuTime="${value}000"
+    fi
+  done </sys/fs/cgroup/cpu.stat
+elif [ -f /sys/fs/cgroup/cpuacct/cpuacct.usage ]; then
+  cpuTime=$(cat /sys/fs/cgroup/cpuacct/cpuacct.usage)
+fi
+
+for pidsEvents in /sys/fs/cgroup/pids.events /sys/fs/cgroup/pids/pids.events; do
+  if [ -f "$pidsEvents" ]; then
+    while read -r key value; do
+      if [ "$key" = "max" ]; then
+        processLimitEvents="$value"
+      fi
+    done <"$pidsEvents"
+    break
+  fi
+done
+
+echo "*-COMPILE::EOF-*" "${runtime:--}" "${peakMemory:--}" "${cpuTime:--}" "${processLimitEvents:--}"
+echo "${status:-run}" "${signal:--}" >"${statusFile}"
+
+exit $exitCode
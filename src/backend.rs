@@ -0,0 +1,1977 @@
+use crate::sandbox::{sanitize_id, unique_suffix, CancellationToken, ContainerExit, RESOURCE_USAGE_FILE, PreparedPaths, SandboxError, SandboxRequest, SandboxResponse, COMPILE_FAILED_STATUS, NO_SIGNAL_STATUS, RUN_STATUS, SCRIPT_FILE, STANDARD_INPUT_FILE, STATUS_FILE};
+use std::{env, fs, io};
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Mutex, MutexGuard};
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The user id that the code is executed as within the container when the request does not provide
+/// a user id, this is the nobody user.
+const DEFAULT_RUN_AS_UID: u32 = 65534;
+
+/// The max number of processes that can exist at once within the container when the request does
+/// not provide a limit, this is enough for runtimes which start many threads (e.g the jvm).
+const DEFAULT_PIDS_LIMIT: u32 = 128;
+
+/// The environment variables that are set for the executed code unless overridden by the request,
+/// ensuring the locale and timezone of the output does not depend on the image or host.
+const DEFAULT_ENV: [(&str, &str); 3] = [("LANG", "C.UTF-8"), ("LC_ALL", "C.UTF-8"), ("TZ", "UTC")];
+
+/// A backend that executes a prepared request, the sandbox prepares the environment of the request
+/// and then executes the request with the backend once per test. This allows the request to be
+/// executed by something other than a container (e.g a fake backend returning canned output).
+pub trait ExecutionBackend: Sync {
+    /// Ensures that the backend is able to execute the given request, this is called before the
+    /// sandbox environment is prepared. By default there is nothing to ensure.
+    ///
+    /// # Arguments
+    /// * `request` - The request that is about to be executed.
+    fn ensure_available(&self, _request: &SandboxRequest) -> Result<(), SandboxError> {
+        Ok(())
+    }
+
+    /// Executes the prepared request a single time, waiting for the execution to complete. If the
+    /// execution runs for longer than the given timeout it is stopped and the response is marked
+    /// as timed out rather than returning a error.
+    ///
+    /// # Arguments
+    /// * `prepared` - The paths of the files that were written when the request was prepared.
+    /// * `request` - The request that is being executed.
+    /// * `timeout` - The max amount of time the execution is allowed to run for.
+    fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<SandboxResponse, SandboxError>;
+
+    /// The command that the backend would execute for the prepared request, the binary followed by
+    /// each of the arguments, without executing anything. This allows the exact command to be
+    /// inspected when debugging or auditing. By default a backend has no command to inspect and
+    /// the command is empty.
+    ///
+    /// # Arguments
+    /// * `prepared` - The paths of the files that were written when the request was prepared.
+    /// * `request` - The request the command is being built for.
+    fn build_command(&self, _prepared: &PreparedPaths, _request: &SandboxRequest) -> Result<Vec<String>, SandboxError> {
+        Ok(Vec::new())
+    }
+}
+
+/// A reference to a backend is also a backend, allowing a single backend (e.g a warm backend and
+/// its running containers) to be shared between many sandboxes.
+impl<B: ExecutionBackend + ?Sized> ExecutionBackend for &B {
+    fn ensure_available(&self, request: &SandboxRequest) -> Result<(), SandboxError> {
+        (**self).ensure_available(request)
+    }
+
+    fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+        (**self).run(prepared, request, timeout)
+    }
+
+    fn build_command(&self, prepared: &PreparedPaths, request: &SandboxRequest) -> Result<Vec<String>, SandboxError> {
+        (**self).build_command(prepared, request)
+    }
+}
+
+/// The backend that executes requests within a container of the related compiler, using the docker
+/// command line (or a runtime which is command line compatible with docker, e.g podman). This is
+/// the default backend of the sandbox.
+#[derive(Debug, Clone)]
+pub struct DockerBackend<'a> {
+    runtime: ContainerRuntime,
+    runtime_binary: Option<&'a str>,
+    pull_images: bool,
+    capture_logs: bool,
+    compile_cache: Option<&'a Path>,
+}
+
+impl<'a> DockerBackend<'a> {
+    /// Creates a new docker backend using the docker runtime, which pulls missing images and does
+    /// not capture the logs of the container.
+    pub fn new() -> DockerBackend<'a> {
+        DockerBackend {
+            runtime: ContainerRuntime::default(),
+            runtime_binary: None,
+            pull_images: true,
+            capture_logs: false,
+            compile_cache: None,
+        }
+    }
+
+    /// Sets the container runtime that will be used to execute the request, by default this is
+    /// docker.
+    ///
+    /// # Arguments
+    /// * `runtime` - The container runtime that will be used to execute the request.
+    pub fn with_runtime(mut self, runtime: ContainerRuntime) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// Sets the path of the binary that is executed for the container runtime, this is used when
+    /// the runtime is installed at a non standard location or behind a wrapper. By default the
+    /// binary of the container runtime is found on the path, e.g docker.
+    ///
+    /// # Arguments
+    /// * `runtime_binary` - The path of the binary executed for the container runtime.
+    pub fn with_runtime_binary(mut self, runtime_binary: &'a str) -> Self {
+        self.runtime_binary = Some(runtime_binary);
+        self
+    }
+
+    /// Sets if the image of the compiler should be pulled before execution when it does not exist
+    /// locally, by default it is. This can be disabled for environments without registry access.
+    ///
+    /// # Arguments
+    /// * `pull_images` - If missing images should be pulled before execution.
+    pub fn with_image_pulling(mut self, pull_images: bool) -> Self {
+        self.pull_images = pull_images;
+        self
+    }
+
+    /// Sets if the logs of the container should be captured before the container is removed and
+    /// included on the response, by default they are not. This is used when debugging the script.
+    ///
+    /// # Arguments
+    /// * `capture_logs` - If the logs of the container should be captured.
+    pub fn with_container_logs(mut self, capture_logs: bool) -> Self {
+        self.capture_logs = capture_logs;
+        self
+    }
+
+    /// Sets the directory of the compile cache, when set the compiled output of the source code is
+    /// stored within the directory keyed by a hash of the source code and the compiler. Executing
+    /// the same source code again (e.g for each test of a request) restores the compiled output
+    /// rather than compiling again. By default the compiled output is not cached.
+    ///
+    /// When caching, compilers are always compiled and executed in separate containers. The cache
+    /// is only read and written on the host, the compiled output is copied between the cache and
+    /// the artifacts directory of the request, so the cache is never mounted into a container and
+    /// the executed code cannot modify the compiled output of other requests.
+    ///
+    /// # Arguments
+    /// * `compile_cache` - The directory the compiled output of the source code is cached within.
+    pub fn with_compile_cache(mut self, compile_cache: &'a Path) -> Self {
+        self.compile_cache = Some(compile_cache);
+        self
+    }
+
+    /// Checks that the container runtime of the backend is installed and ready to execute
+    /// containers, using the configured runtime binary.
+    pub fn check_runtime_available(&self) -> Result<(), SandboxError> {
+        check_runtime_available(self.runtime_binary())
+    }
+
+    /// The binary that is executed for the container runtime of the backend.
+    fn runtime_binary(&self) -> &'a str {
+        self.runtime_binary.unwrap_or_else(|| self.runtime.binary())
+    }
+
+    /// The directory of the compile cache entry of the given request, returning none when the
+    /// backend has no compile cache or the compiler of the request is a interpreter.
+    ///
+    /// # Arguments
+    /// * `request` - The request the compile cache entry is for.
+    fn compile_cache_entry(&self, request: &SandboxRequest) -> Option<PathBuf> {
+        self.compile_cache
+            .filter(|_| !request.compiler.interpreter)
+            .map(|compile_cache| compile_cache.join(compile_cache_key(request)))
+    }
+
+    /// If the given request is compiled and executed in separate containers, which is the case for
+    /// compilers with a runtime image or when caching the compiled output.
+    ///
+    /// # Arguments
+    /// * `request` - The request that is being executed.
+    fn is_staged(&self, request: &SandboxRequest) -> bool {
+        !request.compiler.interpreter && (request.compiler.runtime_image.is_some() || self.compile_cache.is_some())
+    }
+
+    /// Compiles the source code within a container of the image of the compiler and then executes
+    /// the compiled output within a separate container of the runtime image of the compiler. The
+    /// compiled output is passed between the containers through the artifacts directory of the
+    /// request path, which both containers mount to /tmp (instead of any tmpfs of the request). The
+    /// run container is only started if the compiling succeeded and is given the remainder of the
+    /// timeout.
+    ///
+    /// When caching, a complete entry of the compile cache is copied into the artifacts directory
+    /// instead of starting the compile container, otherwise the compiled output is stored in the
+    /// entry once compiled and before any of the code is executed.
+    ///
+    /// # Arguments
+    /// * `paths` - The prepared paths of the files of the request.
+    /// * `request` - The request that is being executed.
+    /// * `timeout` - The max amount of time both containers are allowed to run for.
+    fn run_stages(&self, paths: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<ContainerExit, SandboxError> {
+        let artifacts = paths.directory.join(ARTIFACTS_DIRECTORY);
+
+        // The directory is writable by everyone since the compiled output is written by the
+        // compiler, which is not running as the current user.
+        fs::create_dir_all(&artifacts)?;
+        fs::set_permissions(&artifacts, fs::Permissions::from_mode(0o777))?;
+
+        let cache_entry = self.compile_cache_entry(request);
+
+        if let Some(entry) = cache_entry.as_ref().filter(|entry| entry.join(COMPILE_CACHE_COMPLETE_FILE).is_file()) {
+            copy_directory(&entry.join(COMPILE_CACHE_OUTPUT_DIRECTORY), &artifacts)?;
+
+            let executed = self.run_container(paths, request, timeout, ScriptStage::Run)?;
+            return Ok(ContainerExit { compile_cache_hit: true, ..executed });
+        }
+
+        let compiled = self.run_container(paths, request, timeout, ScriptStage::Compile)?;
+
+        if compiled.timed_out || compiled.cancelled || compiled.status_code != Some(0) {
+            return Ok(compiled);
+        }
+
+        if let Some(entry) = &cache_entry {
+            store_compile_cache_entry(entry, &artifacts)?;
+        }
+
+        let executed = self.run_container(paths, request, timeout.saturating_sub(compiled.duration), ScriptStage::Run)?;
+
+        let logs = match (compiled.logs, executed.logs) {
+            (Some(compiled_logs), Some(executed_logs)) => Some(compiled_logs + &executed_logs),
+            (compiled_logs, executed_logs) => compiled_logs.or(executed_logs),
+        };
+
+        Ok(ContainerExit {
+            duration: compiled.duration + executed.duration,
+            started_at: compiled.started_at,
+            logs,
+            ..executed
+        })
+    }
+
+    /// Runs the container of the related compiler against the prepared request path, waiting for
+    /// the container to exit. If the container runs for longer than the given timeout it is killed
+    /// and the exit of the container is marked as timed out.
+    ///
+    /// # Arguments
+    /// * `paths` - The prepared paths of the files of the request.
+    /// * `request` - The request that is being executed.
+    /// * `timeout` - The max amount of time the container is allowed to run for.
+    /// * `stage` - The stage of the execution the container is running.
+    fn run_container(&self, paths: &PreparedPaths, request: &SandboxRequest, timeout: Duration, stage: ScriptStage) -> Result<ContainerExit, SandboxError> {
+        let container_name = container_name(request.id)?;
+        let runtime_binary = self.runtime_binary();
+        let image = stage_image(request, stage);
+
+        let mut command = Command::new(runtime_binary);
+        command.args(self.run_args(&container_name, paths, request, stage));
+
+        let watched = run_watched(&mut command, runtime_binary, &container_name, timeout, request.cancellation);
+
+        let state = inspect_container(runtime_binary, &container_name);
+
+        let logs = if self.capture_logs {
+            Some(container_logs(runtime_binary, &container_name))
+        } else {
+            None
+        };
+
+        remove_container(runtime_binary, &container_name)?;
+
+        let status = watched.status?;
+        let logs = logs.transpose()?;
+
+        // A container which could not be started (so cannot be inspected) is most often caused by
+        // the image not existing, which happens when images are not being pulled.
+        if state.is_none() && status.code() == Some(RUNTIME_FAILED_STATUS) && !image_exists(runtime_binary, image)? {
+            return Err(SandboxError::ImageNotFound(image.to_string()));
+        }
+
+        // The exit code reported by inspecting the container is preferred over the status code of
+        // the container runtime, which also reports failures of the runtime itself.
+        let status_code = state.map(|state| state.exit_code).or_else(|| status.code());
+        let oom_killed = state.map(|state| state.oom_killed);
+
+        Ok(ContainerExit {
+            status_code,
+            duration: watched.duration,
+            started_at: watched.started_at,
+            finished_at: watched.finished_at,
+            timed_out: watched.timed_out,
+            cancelled: watched.cancelled,
+            logs,
+            oom_killed,
+            compile_cache_hit: false,
+        })
+    }
+
+    /// The arguments of the container runtime which run the container of the given stage, using
+    /// the given name for the container.
+    ///
+    /// # Arguments
+    /// * `container_name` - The name of the container being run.
+    /// * `paths` - The prepared paths of the files of the request.
+    /// * `request` - The request that is being executed.
+    /// * `stage` - The stage of the execution the container is running.
+    fn run_args(&self, container_name: &str, paths: &PreparedPaths, request: &SandboxRequest, stage: ScriptStage) -> Vec<String> {
+        // When executing in stages /tmp is the artifacts directory, which cannot also be a tmpfs.
+        let limits = match stage {
+            ScriptStage::All => container_args(request),
+            _ => container_args(&SandboxRequest { tmpfs_size: None, ..*request }),
+        };
+
+        // Run the container with the request path mounted, this is where the script, the source
+        // code and the output files are all located. The container is not removed automatically,
+        // it is inspected (and the logs are read) once it has exited and then removed.
+        let mut args = vec!["run".to_string(), "--name".to_string(), container_name.to_string()];
+        args.extend(limits);
+
+        for (key, value) in execution_env(request) {
+            args.extend(["-e".to_string(), format!("{}={}", key, value)]);
+        }
+
+        // The request path is mounted read only so the executed code cannot modify the source or
+        // the script, only the output files are mounted writable on top of the read only mount.
+        // Compilers therefore write their compiled output outside of the mount (e.g /tmp).
+        args.extend(["-v".to_string(), format!("{}:/input:ro", paths.directory.display())]);
+
+        for (output_path, output_file) in [(&paths.stdout_file, request.compiler.standard_output_file),
+                                           (&paths.stderr_file, request.compiler.standard_error_file),
+                                           (&paths.usage_file, RESOURCE_USAGE_FILE),
+                                           (&paths.status_file, STATUS_FILE)] {
+            args.extend(["-v".to_string(), format!("{}:/input/{}", output_path.display(), output_file)]);
+        }
+
+        if stage != ScriptStage::All {
+            args.extend(["-v".to_string(), format!("{}:/tmp", paths.directory.join(ARTIFACTS_DIRECTORY).display())]);
+        }
+
+        args.extend(["-w".to_string(), "/input".to_string(), stage_image(request, stage).to_string()]);
+        args.extend(script_args(request, stage));
+        args
+    }
+}
+
+impl Default for DockerBackend<'_> {
+    fn default() -> Self {
+        DockerBackend::new()
+    }
+}
+
+impl ExecutionBackend for DockerBackend<'_> {
+    fn ensure_available(&self, request: &SandboxRequest) -> Result<(), SandboxError> {
+        if self.pull_images {
+            ensure_image(self.runtime_binary(), request.compiler.virtual_machine_name)?;
+
+            if let Some(runtime_image) = request.compiler.runtime_image.filter(|_| !request.compiler.interpreter) {
+                ensure_image(self.runtime_binary(), runtime_image)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+        let container_exit = if self.is_staged(request) {
+            self.run_stages(prepared, request, timeout)?
+        } else {
+            self.run_container(prepared, request, timeout, ScriptStage::All)?
+        };
+
+        Ok(SandboxResponse::from_request(request, prepared, container_exit)?)
+    }
+
+    /// When the request is executed in stages this is the command of the compile stage, the run
+    /// stage is only executed once the compiling has succeeded. The compile cache is not checked,
+    /// and the container is given a new unique name each time the command is built.
+    fn build_command(&self, prepared: &PreparedPaths, request: &SandboxRequest) -> Result<Vec<String>, SandboxError> {
+        let stage = if self.is_staged(request) {
+            ScriptStage::Compile
+        } else {
+            ScriptStage::All
+        };
+
+        let mut command = vec![self.runtime_binary().to_string()];
+        command.extend(self.run_args(&container_name(request.id)?, prepared, request, stage));
+        Ok(command)
+    }
+}
+
+/// The image of the container executing the given stage of the request, the run stage is executed
+/// within the runtime image of the compiler (if any) and every other stage within the image of the
+/// compiler.
+///
+/// # Arguments
+/// * `request` - The request that is being executed.
+/// * `stage` - The stage of the execution the container is running.
+fn stage_image<'a>(request: &SandboxRequest<'a>, stage: ScriptStage) -> &'a str {
+    match (stage, request.compiler.runtime_image) {
+        (ScriptStage::Run, Some(runtime_image)) => runtime_image,
+        _ => request.compiler.virtual_machine_name,
+    }
+}
+
+/// The errors reported by the container runtime (in lowercase) when pulling a image which does not
+/// exist, or which the registry does not allow access to.
+const IMAGE_NOT_FOUND_ERRORS: [&str; 4] = ["not found", "does not exist", "manifest unknown", "pull access denied"];
+
+/// The status code of the container runtime when the container could not be created or started,
+/// rather than the status code of the executed code.
+const RUNTIME_FAILED_STATUS: i32 = 125;
+
+/// The interval at which a running execution checks if it has reached its timeout or has been
+/// cancelled.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The amount of time the container runtime is given to exit once the timeout of the execution has
+/// been reached and the container has been killed, after which the runtime itself is killed.
+const RUNTIME_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// The reason a execution was stopped before it exited by itself.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum StopReason {
+    /// The execution ran for longer than its timeout.
+    TimedOut,
+    /// The request of the execution was cancelled.
+    Cancelled,
+}
+
+/// The name of the directory within the request path that the compiled output is written to when
+/// compiling and running in separate containers, this is mounted to /tmp within both containers.
+const ARTIFACTS_DIRECTORY: &str = "artifacts";
+
+/// The stage of the execution that a container is running, when compiling and running in separate
+/// containers the script is executed once for each stage, otherwise a single container runs both.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum ScriptStage {
+    /// The source code is compiled (if required) and then executed.
+    All,
+    /// The source code is only compiled, writing the compiled output to the artifacts directory.
+    Compile,
+    /// The compiled output in the artifacts directory is executed.
+    Run,
+}
+
+impl ScriptStage {
+    /// The argument given to the script for the stage.
+    fn argument(&self) -> &'static str {
+        match self {
+            ScriptStage::All => "",
+            ScriptStage::Compile => "compile",
+            ScriptStage::Run => "run",
+        }
+    }
+}
+
+/// The name of the directory within a compile cache entry that the compiled output is stored in.
+const COMPILE_CACHE_OUTPUT_DIRECTORY: &str = "output";
+
+/// The name of the file within a compile cache entry which is written once the compiled output has
+/// been completely stored, a entry without this file is compiled again.
+const COMPILE_CACHE_COMPLETE_FILE: &str = "complete";
+
+/// The number of idle containers that are kept alive for each image by a warm backend when no
+/// number has been given.
+const DEFAULT_WARM_CONTAINERS: usize = 2;
+
+/// The command executed within a warm container once an execution has completed, killing any
+/// processes left behind by the executed code (the main process of the container cannot be killed
+/// from within the container) and removing any files written to the temp directory.
+const WARM_RESET_COMMAND: &str = "kill -9 -1 2>/dev/null; rm -rf /tmp/* /tmp/.[!.]* 2>/dev/null; true";
+
+/// A container that has been started ahead of any execution and is kept alive between executions,
+/// along with the host directory mounted as the working directory of the container.
+#[derive(Debug)]
+struct WarmContainer {
+    /// The name of the running container.
+    name: String,
+    /// The host directory that is mounted to the input directory of the container, the prepared
+    /// files of a request are copied into this directory for each execution.
+    directory: PathBuf,
+}
+
+/// The backend that keeps a number of containers alive for each image and executes requests within
+/// the already running containers (with docker exec), avoiding the cost of starting a new container
+/// for every execution. Before each execution the prepared files of the request are copied into
+/// the working directory of the container, and once completed the outputs are copied back and the
+/// working directory and temp directory of the container are reset.
+///
+/// Containers are only reused between requests with the same image and limits, a container which
+/// timed out or failed to reset is removed rather than reused. Unlike the docker backend the
+/// working directory is writable by the executed code, since it is reset between executions, and
+/// the logs of the container are never captured. Compilers with a runtime image are still compiled
+/// and executed within the same container of the image of the compiler, since the container is
+/// started ahead of the execution, and the compile cache of the docker backend is not used since
+/// the compiled output never leaves the container. Any idle containers are removed once the backend
+/// is dropped, so a single backend should be shared between sandboxes by reference.
+#[derive(Debug)]
+pub struct WarmDockerBackend<'a> {
+    docker: DockerBackend<'a>,
+    warm_containers: usize,
+    idle: Mutex<HashMap<String, Vec<WarmContainer>>>,
+}
+
+impl<'a> WarmDockerBackend<'a> {
+    /// Creates a new warm backend using the runtime and image pulling of the given docker backend,
+    /// keeping up to two idle containers alive for each image.
+    ///
+    /// # Arguments
+    /// * `docker` - The docker backend that the containers are started with.
+    pub fn new(docker: DockerBackend<'a>) -> WarmDockerBackend<'a> {
+        WarmDockerBackend {
+            docker,
+            warm_containers: DEFAULT_WARM_CONTAINERS,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the max number of idle containers that are kept alive for each image, once completed
+    /// any containers over this number are removed rather than kept. By default this is two.
+    ///
+    /// # Arguments
+    /// * `warm_containers` - The max number of idle containers kept alive for each image.
+    pub fn with_warm_containers(mut self, warm_containers: usize) -> Self {
+        self.warm_containers = warm_containers;
+        self
+    }
+
+    /// The number of idle containers that are currently alive and waiting to be reused.
+    pub fn idle_containers(&self) -> usize {
+        self.lock_idle().values().map(Vec::len).sum()
+    }
+
+    /// Locks the idle containers, a panic while the lock was held cannot leave the idle containers
+    /// in an invalid state so a poisoned lock is still used.
+    fn lock_idle(&self) -> MutexGuard<'_, HashMap<String, Vec<WarmContainer>>> {
+        self.idle.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Starts a new container of the image of the given request with the limits of the request,
+    /// the container is kept running (doing nothing) until the request is executed within it.
+    ///
+    /// # Arguments
+    /// * `request` - The request the container is being started for.
+    fn start_container(&self, request: &SandboxRequest) -> Result<WarmContainer, SandboxError> {
+        let name = container_name(request.id)?;
+        let directory = env::temp_dir().join(&name);
+
+        // The directory is writable by everyone since the executed code is not running as the
+        // current user, but must be able to write its output files into the directory.
+        fs::create_dir_all(&directory)?;
+        fs::set_permissions(&directory, fs::Permissions::from_mode(0o777))?;
+
+        let container = WarmContainer { name, directory };
+
+        let mut command = Command::new(self.docker.runtime_binary());
+        command.args(["run", "-d", "--name", &container.name]).args(container_args(request));
+        command.arg("-v").arg(format!("{}:/input", container.directory.display()));
+
+        let status = command
+            .args(["-w", "/input"])
+            .arg(request.compiler.virtual_machine_name)
+            .args(["sleep", "infinity"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        match status {
+            Ok(status) if status.success() => Ok(container),
+            status => {
+                self.remove(container);
+                Err(status.map(|status| SandboxError::ContainerFailed(status.code().unwrap_or(-1)))
+                    .unwrap_or_else(SandboxError::Io))
+            }
+        }
+    }
+
+    /// Executes the prepared request within the given warm container, copying the prepared files
+    /// into the container and the output files back once the execution has completed.
+    ///
+    /// # Arguments
+    /// * `container` - The warm container the request is executed within.
+    /// * `paths` - The prepared paths of the files of the request.
+    /// * `request` - The request that is being executed.
+    /// * `timeout` - The max amount of time the execution is allowed to run for.
+    fn exec_container(&self, container: &WarmContainer, paths: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<ContainerExit, SandboxError> {
+        for file in [&paths.script_file, &paths.source_file, &paths.stdin_file, &paths.stdout_file, &paths.stderr_file, &paths.usage_file, &paths.status_file] {
+            if let Some(file_name) = file.file_name() {
+                fs::copy(file, container.directory.join(file_name))?;
+            }
+        }
+
+        let runtime_binary = self.docker.runtime_binary();
+
+        let mut command = Command::new(runtime_binary);
+        command.arg("exec").args(["-w", "/input"]);
+
+        for (key, value) in execution_env(request) {
+            command.arg("-e").arg(format!("{}={}", key, value));
+        }
+
+        command.arg(&container.name).args(script_args(request, ScriptStage::All)).stdin(Stdio::null());
+
+        let watched = run_watched(&mut command, runtime_binary, &container.name, timeout, request.cancellation);
+
+        for (output_path, output_file) in [(&paths.stdout_file, request.compiler.standard_output_file),
+                                           (&paths.stderr_file, request.compiler.standard_error_file),
+                                           (&paths.usage_file, RESOURCE_USAGE_FILE),
+                                           (&paths.status_file, STATUS_FILE)] {
+            fs::copy(container.directory.join(output_file), output_path)?;
+        }
+
+        Ok(ContainerExit {
+            status_code: watched.status?.code(),
+            duration: watched.duration,
+            started_at: watched.started_at,
+            finished_at: watched.finished_at,
+            timed_out: watched.timed_out,
+            cancelled: watched.cancelled,
+            logs: None,
+            oom_killed: None,
+            compile_cache_hit: false,
+        })
+    }
+
+    /// Resets the given container so that nothing is left behind by the previous execution, both
+    /// the working directory and the temp directory of the container are emptied. Returning if the
+    /// container was reset and can be reused.
+    ///
+    /// # Arguments
+    /// * `container` - The warm container being reset.
+    fn reset(&self, container: &WarmContainer) -> bool {
+        let cleared = fs::read_dir(&container.directory)
+            .and_then(|mut entries| entries.try_for_each(|entry| fs::remove_file(entry?.path())))
+            .is_ok();
+
+        cleared && Command::new(self.docker.runtime_binary())
+            .args(["exec", &container.name, "sh", "-c", WARM_RESET_COMMAND])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    /// Removes the given container and the host directory mounted within the container.
+    ///
+    /// # Arguments
+    /// * `container` - The warm container being removed.
+    fn remove(&self, container: WarmContainer) {
+        let _ = remove_container(self.docker.runtime_binary(), &container.name);
+        let _ = fs::remove_dir_all(&container.directory);
+    }
+}
+
+impl ExecutionBackend for WarmDockerBackend<'_> {
+    fn ensure_available(&self, request: &SandboxRequest) -> Result<(), SandboxError> {
+        self.docker.ensure_available(request)
+    }
+
+    fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+        // Containers are keyed by the image and the limits the container was started with, since
+        // the limits of a container cannot be changed once started.
+        let key = format!("{} {}", request.compiler.virtual_machine_name, container_args(request).join(" "));
+
+        let idle = self.lock_idle().get_mut(&key).and_then(Vec::pop);
+
+        let container = match idle {
+            Some(container) => container,
+            None => self.start_container(request)?,
+        };
+
+        let container_exit = self.exec_container(&container, prepared, request, timeout);
+        let reusable = matches!(container_exit, Ok(ContainerExit { timed_out: false, cancelled: false, .. })) && self.reset(&container);
+
+        let mut idle = self.lock_idle();
+        let containers = idle.entry(key).or_default();
+
+        if reusable && containers.len() < self.warm_containers {
+            containers.push(container);
+        } else {
+            drop(idle);
+            self.remove(container);
+        }
+
+        Ok(SandboxResponse::from_request(request, prepared, container_exit?)?)
+    }
+}
+
+impl Drop for WarmDockerBackend<'_> {
+    fn drop(&mut self) {
+        let idle = std::mem::take(&mut *self.lock_idle());
+
+        for container in idle.into_values().flatten() {
+            self.remove(container);
+        }
+    }
+}
+
+/// The backend that executes requests directly on the host as child processes, without any
+/// container. The compiler of the request must be installed on the host and is executed within the
+/// request path, with the same timeout and output capture as the docker backend.
+///
+/// This backend provides no isolation at all, the executed code has the same access to the host as
+/// the current process and the memory, cpu, network and user settings of the request are ignored.
+/// It must only be used for trusted code, e.g during development when docker is not running.
+///
+/// Compilers write their compiled output to /tmp within the container, when executing locally any
+/// argument (or environment variable) within /tmp is instead within a temp directory of the request
+/// path. So concurrent requests never share (or overwrite) the compiled output of each other.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LocalBackend;
+
+impl LocalBackend {
+    /// Creates a new local backend.
+    pub fn new() -> LocalBackend {
+        LocalBackend
+    }
+}
+
+impl ExecutionBackend for LocalBackend {
+    fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+        let compiler = request.compiler;
+        let deadline = Instant::now() + timeout;
+
+        // The output of the compiler and the output of the compiled code are both written to the
+        // same output files, the same as the script executed within the container. When merging
+        // the outputs the standard error output is written to the standard output file.
+        let stdout = OpenOptions::new().append(true).open(&prepared.stdout_file)?;
+
+        let stderr = if request.merge_output {
+            stdout.try_clone()?
+        } else {
+            OpenOptions::new().append(true).open(&prepared.stderr_file)?
+        };
+
+        let temp_directory = prepared.directory.join(LOCAL_TEMP_DIRECTORY);
+        fs::create_dir_all(&temp_directory)?;
+
+        let local_args = |args: &str| -> Vec<String> {
+            args.split_whitespace().map(|arg| local_temp_path(arg, &temp_directory)).collect()
+        };
+
+        let local_command = |program: &str| -> io::Result<Command> {
+            let mut parts = local_args(program).into_iter();
+            let mut command = Command::new(parts.next().unwrap_or_else(|| program.to_string()));
+
+            command
+                .args(parts)
+                .current_dir(&prepared.directory)
+                .envs(execution_env(request).map(|(key, value)| (key, local_temp_path(value, &temp_directory))))
+                .stdout(stdout.try_clone()?)
+                .stderr(stderr.try_clone()?);
+
+            Ok(command)
+        };
+
+        let started_at = SystemTime::now();
+        let started = Instant::now();
+
+        // Compilers that are not interpreters first compile the source code, only executing the
+        // compiled output if compiling succeeded. Otherwise the compile stage is written to the
+        // status file so that the response is marked as failing at the compile stage, along with
+        // the signal that terminated the process (if any) the same as the script.
+        let mut compile = local_command(compiler.compiler)?;
+        compile.args(local_args(compiler.additional_arguments.unwrap_or(""))).arg(&prepared.source_file);
+
+        let cancellation = request.cancellation;
+
+        let (status, stopped, stage) = match compiler.compiled_output {
+            None => {
+                let (status, stopped) = run_local(compile.args(request.program_args).arg("-").stdin(File::open(&prepared.stdin_file)?), deadline, cancellation)?;
+                (status, stopped, RUN_STATUS)
+            }
+            Some(compiled_output) => match run_local(compile.stdin(Stdio::null()), deadline, cancellation)? {
+                (status, None) if status.success() => {
+                    let mut execute = local_command(compiled_output)?;
+                    let (status, stopped) = run_local(execute.args(request.program_args).arg("-").stdin(File::open(&prepared.stdin_file)?), deadline, cancellation)?;
+                    (status, stopped, RUN_STATUS)
+                }
+                (status, stopped) => (status, stopped, COMPILE_FAILED_STATUS),
+            },
+        };
+
+        if stopped.is_none() {
+            let signal = local_signal(status).map(|signal| signal.to_string());
+            fs::write(&prepared.status_file, format!("{} {}\n", stage, signal.as_deref().unwrap_or(NO_SIGNAL_STATUS)))?;
+        }
+
+        let container_exit = ContainerExit {
+            status_code: local_status_code(status),
+            duration: started.elapsed(),
+            started_at,
+            finished_at: SystemTime::now(),
+            timed_out: stopped == Some(StopReason::TimedOut),
+            cancelled: stopped == Some(StopReason::Cancelled),
+            logs: None,
+            oom_killed: None,
+            compile_cache_hit: false,
+        };
+        Ok(SandboxResponse::from_request(request, prepared, container_exit)?)
+    }
+}
+
+/// The environment variables that are set for the code executed for the given request, the
+/// default environment variables that are not overridden followed by the environment variables of
+/// the compiler that are not overridden and then the environment variables of the request.
+///
+/// # Arguments
+/// * `request` - The request that is being executed.
+fn execution_env<'a>(request: &'a SandboxRequest) -> impl Iterator<Item = (&'a str, &'a str)> {
+    let compiler_env = request.compiler.env;
+    let overridden = move |key: &str| request.env.iter().any(|(request_key, _)| *request_key == key);
+
+    DEFAULT_ENV.iter()
+        .filter(move |(key, _)| !overridden(key) && !compiler_env.iter().any(|(compiler_key, _)| compiler_key == key))
+        .chain(compiler_env.iter().filter(move |(key, _)| !overridden(key)))
+        .chain(request.env.iter())
+        .copied()
+}
+
+/// The arguments of the container runtime which apply the limits of the given request to the
+/// container, these are the same for every execution of the request. The memory swap limit is set
+/// to the same value as the memory limit, otherwise the container would be able to continue to
+/// allocate into swap once the memory limit was reached. The tmpfs is mounted executable since the
+/// compiled output is executed from /tmp.
+///
+/// # Arguments
+/// * `request` - The request that is being executed.
+fn container_args(request: &SandboxRequest) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if let Some(memory_limit) = request.memory_limit {
+        args.extend(["--memory".to_string(), format!("{}b", memory_limit)]);
+        args.extend(["--memory-swap".to_string(), format!("{}b", memory_limit)]);
+    }
+
+    if let Some(cpu_limit) = request.cpu_limit {
+        args.extend(["--cpus".to_string(), cpu_limit.to_string()]);
+    }
+
+    if !request.network_enabled {
+        args.extend(["--network".to_string(), "none".to_string()]);
+    }
+
+    args.extend(["--user".to_string(), request.run_as_uid.unwrap_or(DEFAULT_RUN_AS_UID).to_string()]);
+    args.extend(["--pids-limit".to_string(), request.pids_limit.unwrap_or(DEFAULT_PIDS_LIMIT).to_string()]);
+
+    if let Some(tmpfs_size) = request.tmpfs_size {
+        args.extend(["--tmpfs".to_string(), format!("/tmp:rw,exec,nosuid,size={}", tmpfs_size)]);
+    }
+
+    if let Some(seccomp_profile) = request.seccomp_profile {
+        args.extend(["--security-opt".to_string(), format!("seccomp={}", seccomp_profile.display())]);
+    }
+
+    args
+}
+
+/// The command that is executed within the container for the given request, executing the script
+/// with the details of the compiler. When merging the outputs the script is given the standard
+/// output file for both outputs, which the script then redirects to the same open file. The
+/// arguments of the executed code follow the details of the compiler as separate arguments.
+///
+/// The command is given to the container runtime as a vector of arguments rather than a single
+/// shell string, so none of the values of the request are ever interpreted by a shell before the
+/// script receives them.
+///
+/// # Arguments
+/// * `request` - The request that is being executed.
+/// * `stage` - The stage of the execution the script is running.
+fn script_args(request: &SandboxRequest, stage: ScriptStage) -> Vec<String> {
+    let standard_error_file = if request.merge_output {
+        request.compiler.standard_output_file
+    } else {
+        request.compiler.standard_error_file
+    };
+
+    let mut args = vec![
+        "sh".to_string(),
+        format!("./{}", SCRIPT_FILE),
+        request.compiler.compiler.to_string(),
+        request.compiler.source_file_name(),
+        STANDARD_INPUT_FILE.to_string(),
+        request.compiler.compiled_output.unwrap_or("").to_string(),
+        request.compiler.additional_arguments.unwrap_or("").to_string(),
+        request.compiler.standard_output_file.to_string(),
+        standard_error_file.to_string(),
+        stage.argument().to_string(),
+        if request.measure_resources { RESOURCE_USAGE_FILE } else { "" }.to_string(),
+        STATUS_FILE.to_string(),
+    ];
+
+    args.extend(request.program_args.iter().map(|arg| arg.to_string()));
+    args
+}
+
+/// The key of the compile cache entry of the given request, a hash of the source code and all the
+/// details of the compiler which change the compiled output. Any change to the source code or the
+/// compiler results in a different entry.
+///
+/// # Arguments
+/// * `request` - The request the key is being generated for.
+fn compile_cache_key(request: &SandboxRequest) -> String {
+    let compiler = request.compiler;
+    let mut hasher = DefaultHasher::new();
+
+    request.source_code.hash(&mut hasher);
+    compiler.compiler.hash(&mut hasher);
+    compiler.source_file_name().hash(&mut hasher);
+    compiler.additional_arguments.hash(&mut hasher);
+    compiler.compiled_output.hash(&mut hasher);
+    compiler.virtual_machine_name.hash(&mut hasher);
+
+    format!("{:016x}", hasher.finish())
+}
+
+/// Stores the compiled output within the given artifacts directory as the given entry of the
+/// compile cache. The entry is first written to a unique staging directory which is then renamed
+/// to the entry, so a entry is never seen partially written. When another execution has already
+/// stored the entry the staged copy is discarded.
+///
+/// # Arguments
+/// * `entry` - The directory of the compile cache entry being stored.
+/// * `artifacts` - The artifacts directory containing the compiled output.
+fn store_compile_cache_entry(entry: &Path, artifacts: &Path) -> io::Result<()> {
+    let staging = entry.with_extension(format!("{:016x}", unique_suffix()));
+
+    copy_directory(artifacts, &staging.join(COMPILE_CACHE_OUTPUT_DIRECTORY))?;
+    File::create(staging.join(COMPILE_CACHE_COMPLETE_FILE))?;
+
+    if fs::rename(&staging, entry).is_err() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    Ok(())
+}
+
+/// Recursively copies the files of the given directory into the other directory, creating the
+/// directory (and any sub directories) when missing. The permissions of the files are kept, so a
+/// compiled output stays executable.
+///
+/// # Arguments
+/// * `from` - The directory being copied.
+/// * `to` - The directory the files are copied into.
+fn copy_directory(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            copy_directory(&entry.path(), &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Starts the watchdog of the container with the given name, if the container has not completed
+/// before the timeout (or the request is cancelled) then the container is killed. Completion of the
+/// container is signaled to the watchdog by sending down the returned channel (or the sender being
+/// dropped), joining the watchdog returns the reason the container was killed, if it was.
+///
+/// # Arguments
+/// * `binary` - The binary of the container runtime.
+/// * `container_name` - The name of the container being watched.
+/// * `timeout` - The max amount of time the container is allowed to run for.
+/// * `cancellation` - The token of the request that the container is killed on cancellation of.
+fn spawn_watchdog(binary: &str, container_name: &str, timeout: Duration, cancellation: Option<&CancellationToken>)
+                  -> (mpsc::Sender<()>, thread::JoinHandle<Option<StopReason>>) {
+    let (completed_sender, completed_receiver) = mpsc::channel::<()>();
+    let binary = binary.to_string();
+    let container_name = container_name.to_string();
+    let cancellation = cancellation.cloned();
+    let deadline = Instant::now() + timeout;
+
+    let watchdog = thread::spawn(move || {
+        // The watchdog wakes up at a interval to check if the request has been cancelled, rather
+        // than only waking up once the timeout has been reached.
+        let stopped = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                break StopReason::TimedOut;
+            }
+
+            match completed_receiver.recv_timeout(remaining.min(STOP_POLL_INTERVAL)) {
+                Err(mpsc::RecvTimeoutError::Timeout) if cancellation.as_ref().is_some_and(CancellationToken::is_cancelled) => {
+                    break StopReason::Cancelled;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                _ => return None,
+            }
+        };
+
+        let _ = Command::new(binary)
+            .args(["kill", &container_name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        Some(stopped)
+    });
+
+    (completed_sender, watchdog)
+}
+
+/// The exit of a command of the container runtime that was executed while being watched by the
+/// watchdog of its container, along with when the command was executed.
+#[derive(Debug)]
+struct WatchedExit {
+    /// The exit status of the container runtime, or the error of spawning or waiting on it.
+    status: io::Result<ExitStatus>,
+    /// The amount of time the container runtime was waited on for.
+    duration: Duration,
+    /// The time at which the container runtime was spawned.
+    started_at: SystemTime,
+    /// The time at which the container runtime exited (or was killed).
+    finished_at: SystemTime,
+    /// If the container was killed by the watchdog for reaching its timeout.
+    timed_out: bool,
+    /// If the container was killed by the watchdog for the request being cancelled.
+    cancelled: bool,
+}
+
+/// Executes the given command of the container runtime with the watchdog of the container with the
+/// given name running, waiting for the runtime to exit (but no longer than the timeout and grace
+/// period) and then stopping the watchdog. This is shared by every command that executes the code
+/// of a request within a container, e.g both docker run and docker exec.
+///
+/// # Arguments
+/// * `command` - The command of the container runtime executing the container.
+/// * `binary` - The binary of the container runtime.
+/// * `container_name` - The name of the container being executed.
+/// * `timeout` - The max amount of time the container is allowed to run for.
+/// * `cancellation` - The token of the request that the container is killed on cancellation of.
+fn run_watched(command: &mut Command,
+               binary: &str,
+               container_name: &str,
+               timeout: Duration,
+               cancellation: Option<&CancellationToken>) -> WatchedExit {
+    let (completed_sender, watchdog) = spawn_watchdog(binary, container_name, timeout, cancellation);
+
+    let started_at = SystemTime::now();
+    let started = Instant::now();
+    let status = wait_bounded(command, timeout + RUNTIME_GRACE_PERIOD);
+    let duration = started.elapsed();
+    let finished_at = SystemTime::now();
+
+    let _ = completed_sender.send(());
+    let stopped = watchdog.join().unwrap_or(None);
+
+    WatchedExit {
+        status,
+        duration,
+        started_at,
+        finished_at,
+        timed_out: stopped == Some(StopReason::TimedOut),
+        cancelled: stopped == Some(StopReason::Cancelled),
+    }
+}
+
+/// Spawns the given command of the container runtime and waits for the process to exit, if the
+/// process has not exited by the given timeout then the process is killed and collected. The
+/// watchdog kills the container once the timeout of the request is reached, so the runtime only
+/// reaches this timeout when the runtime itself is not responding (e.g the kill of the container
+/// failed). The container is always forcibly removed afterwards, so none are left behind.
+///
+/// # Arguments
+/// * `command` - The command of the container runtime being executed.
+/// * `timeout` - The max amount of time the runtime is waited on for.
+fn wait_bounded(command: &mut Command, timeout: Duration) -> io::Result<ExitStatus> {
+    let mut child = command.spawn()?;
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+
+        if Instant::now() >= deadline {
+            child.kill()?;
+            return child.wait();
+        }
+
+        thread::sleep(STOP_POLL_INTERVAL);
+    }
+}
+
+/// The name of the directory within the request path that replaces /tmp when executing locally.
+const LOCAL_TEMP_DIRECTORY: &str = "tmp";
+
+/// The given argument with the temp directory of the container (/tmp) replaced by the given local
+/// temp directory, any other argument is returned unchanged. e.g /tmp/cpp.o becomes the cpp.o file
+/// within the local temp directory.
+///
+/// # Arguments
+/// * `argument` - The argument (or value of a environment variable) being replaced.
+/// * `temp_directory` - The local temp directory of the request.
+fn local_temp_path(argument: &str, temp_directory: &Path) -> String {
+    match argument.strip_prefix("/tmp") {
+        Some(path) if path.is_empty() || path.starts_with('/') => format!("{}{}", temp_directory.display(), path),
+        _ => argument.to_string(),
+    }
+}
+
+/// Spawns the given command and waits for the process to exit, if the process has not exited by
+/// the deadline (or the request is cancelled) then the process is killed. Returning the exit status
+/// of the process and the reason the process was stopped, if it was.
+///
+/// # Arguments
+/// * `command` - The command of the process being executed.
+/// * `deadline` - The instant at which the process is killed if it has not yet exited.
+/// * `cancellation` - The token of the request that the process is killed on cancellation of.
+fn run_local(command: &mut Command, deadline: Instant, cancellation: Option<&CancellationToken>) -> io::Result<(ExitStatus, Option<StopReason>)> {
+    let mut child = command.spawn()?;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, None));
+        }
+
+        let stopped = if cancellation.is_some_and(CancellationToken::is_cancelled) {
+            Some(StopReason::Cancelled)
+        } else if Instant::now() >= deadline {
+            Some(StopReason::TimedOut)
+        } else {
+            None
+        };
+
+        if stopped.is_some() {
+            child.kill()?;
+            return Ok((child.wait()?, stopped));
+        }
+
+        thread::sleep(STOP_POLL_INTERVAL);
+    }
+}
+
+/// The status code of the given exit status of a local process, mapping the signal that terminated
+/// the process to the status code the shell would report.
+///
+/// # Arguments
+/// * `status` - The exit status of the local process.
+fn local_status_code(status: ExitStatus) -> Option<i32> {
+    local_signal(status).map(|signal| 128 + signal).or_else(|| status.code())
+}
+
+/// The number of the signal that terminated the local process of the given exit status, if it was
+/// terminated by a signal.
+///
+/// # Arguments
+/// * `status` - The exit status of the local process.
+fn local_signal(status: ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Generates a unique name for a container executing the request with the given id, in the form of
+/// sandbox_{id}_{suffix}. The id is sanitized so that it is valid within a container name, and the
+/// random suffix ensures requests with the same id never share a container name.
+///
+/// # Arguments
+/// * `id` - The id of the request the container is executing.
+fn container_name(id: &str) -> Result<String, SandboxError> {
+    let id = sanitize_id(id)?;
+    Ok(format!("sandbox_{}_{:08x}", id, unique_suffix() as u32))
+}
+
+
+/// The state of a container once it has exited, as reported by inspecting the container.
+#[derive(Debug, Copy, Clone)]
+struct ContainerState {
+    /// The exit code of the main process of the container.
+    exit_code: i32,
+    /// If the container was killed by the kernel for exceeding its memory limit.
+    oom_killed: bool,
+}
+
+
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum ContainerRuntime {
+    /// The docker container runtime, this is the default runtime.
+    #[default]
+    Docker,
+    /// The podman container runtime, this is command line compatible with docker and can be used
+    /// when running rootless.
+    Podman,
+}
+
+impl ContainerRuntime {
+    /// The name of the binary that is executed for the container runtime.
+    pub fn binary(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+        }
+    }
+
+    /// Checks that the container runtime is installed and ready to execute containers, this can be
+    /// used at startup to fail fast rather than failing deep in the execution of a request. When
+    /// not available the error describes why the runtime is not available.
+    pub fn check_available(&self) -> Result<(), SandboxError> {
+        check_runtime_available(self.binary())
+    }
+
+    /// Ensures that the given image is available locally, pulling the image if it does not already
+    /// exist. If the image already exists then no pull is performed.
+    ///
+    /// # Arguments
+    /// * `image` - The name of the image that is required to be available.
+    pub fn ensure_image(&self, image: &str) -> Result<(), SandboxError> {
+        ensure_image(self.binary(), image)
+    }
+}
+
+/// Checks that the container runtime of the given binary is installed and ready to execute
+/// containers, by requesting the information of the runtime.
+///
+/// # Arguments
+/// * `binary` - The binary of the container runtime being checked.
+fn check_runtime_available(binary: &str) -> Result<(), SandboxError> {
+    let output = Command::new(binary).arg("info").stdin(Stdio::null()).output();
+
+    match output {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(SandboxError::RuntimeUnavailable(
+            String::from_utf8_lossy(&output.stderr).trim().to_string())),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Err(SandboxError::RuntimeUnavailable(
+            format!("{} is not installed", binary))),
+        Err(error) => Err(SandboxError::Io(error)),
+    }
+}
+
+/// Ensures that the given image is available locally to the container runtime of the given binary,
+/// pulling the image if it does not already exist. If the registry reports that the image does not
+/// exist then a image not found error is returned, rather than the exit code of the pull.
+///
+/// # Arguments
+/// * `binary` - The binary of the container runtime.
+/// * `image` - The name of the image that is required to be available.
+fn ensure_image(binary: &str, image: &str) -> Result<(), SandboxError> {
+    if image_exists(binary, image)? {
+        return Ok(());
+    }
+
+    let pull = Command::new(binary)
+        .args(["pull", image])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()?;
+
+    if pull.status.success() {
+        return Ok(());
+    }
+
+    let error = String::from_utf8_lossy(&pull.stderr).to_ascii_lowercase();
+
+    if IMAGE_NOT_FOUND_ERRORS.iter().any(|not_found| error.contains(not_found)) {
+        Err(SandboxError::ImageNotFound(image.to_string()))
+    } else {
+        Err(SandboxError::ContainerFailed(pull.status.code().unwrap_or(-1)))
+    }
+}
+
+/// Checks if the given image exists locally to the container runtime of the given binary.
+///
+/// # Arguments
+/// * `binary` - The binary of the container runtime.
+/// * `image` - The name of the image being checked.
+fn image_exists(binary: &str, image: &str) -> io::Result<bool> {
+    let status = Command::new(binary)
+        .args(["image", "inspect", image])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+
+    Ok(status.success())
+}
+
+/// Reads the logs of the container with the given name, both the standard output and the standard
+/// error output of the container are included in the logs.
+///
+/// # Arguments
+/// * `binary` - The binary of the container runtime.
+/// * `container_name` - The name of the container the logs are being read from.
+fn container_logs(binary: &str, container_name: &str) -> Result<String, SandboxError> {
+    let output = Command::new(binary)
+        .args(["logs", container_name])
+        .stdin(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(SandboxError::ContainerFailed(output.status.code().unwrap_or(-1)));
+    }
+
+    let mut logs = String::from_utf8_lossy(&output.stdout).into_owned();
+    logs.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    Ok(logs)
+}
+
+/// Inspects the exited container with the given name, returning none if the container could not be
+/// inspected or the state of the container could not be parsed.
+///
+/// # Arguments
+/// * `binary` - The binary of the container runtime.
+/// * `container_name` - The name of the container being inspected.
+fn inspect_container(binary: &str, container_name: &str) -> Option<ContainerState> {
+    let output = Command::new(binary)
+        .args(["inspect", "--format", "{{.State.ExitCode}} {{.State.OOMKilled}}", container_name])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut fields = output.split_whitespace();
+
+    Some(ContainerState {
+        exit_code: fields.next()?.parse().ok()?,
+        oom_killed: fields.next()?.parse().ok()?,
+    })
+}
+
+/// Forcibly removes the container with the given name, a container which no longer exists is not
+/// treated as a error.
+///
+/// # Arguments
+/// * `binary` - The binary of the container runtime.
+/// * `container_name` - The name of the container being removed.
+fn remove_container(binary: &str, container_name: &str) -> Result<(), SandboxError> {
+    Command::new(binary)
+        .args(["rm", "-f", container_name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::{compiler_for, LanguageCompiler, Sandbox, SandboxConfig, COMPILERS};
+    use crate::testing::{registered, MockBackend, SHELL};
+
+    /// A container runtime which logs its arguments to the log file next to it, writing the
+    /// compiled output to the mounted artifacts directory when running the compile stage. The
+    /// containers can never be inspected.
+    const FAKE_RUNTIME: &str = r#"#!/bin/sh
+echo "$*" >> "$0.log"
+
+if [ "$1" = "run" ]; then
+  for arg in "$@"; do
+    case "$arg" in
+      *:/tmp) artifacts="${arg%:/tmp}" ;;
+      compile) echo compiled > "$artifacts/cpp.o" ;;
+    esac
+  done
+elif [ "$1" = "inspect" ]; then
+  exit 1
+fi
+"#;
+
+    /// A container runtime which logs its arguments to the log file next to it, only the image named
+    /// present exists and pulling the images named unknown and broken fails.
+    const PULLING_RUNTIME: &str = r#"#!/bin/sh
+echo "$*" >> "$0.log"
+
+case "$*" in
+  "image inspect present") exit 0 ;;
+  "image inspect "*) exit 1 ;;
+  "pull unknown") echo "Error response from daemon: manifest unknown" >&2; exit 1 ;;
+  "pull broken") echo "Error response from daemon: network is unreachable" >&2; exit 1 ;;
+esac
+"#;
+
+    /// A container runtime which writes the logs of every container to both of its outputs, the
+    /// containers can never be inspected.
+    const LOGGING_RUNTIME: &str = r#"#!/bin/sh
+case "$1" in
+  logs) echo "container output"; echo "container error" >&2 ;;
+  inspect) exit 1 ;;
+esac
+"#;
+
+    /// Writes the given fake container runtime to a new temp directory, returning the directory.
+    fn fake_runtime(script: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("compileme_runtime_{:016x}", unique_suffix()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let runtime = directory.join("runtime");
+        fs::write(&runtime, script).unwrap();
+        fs::set_permissions(&runtime, fs::Permissions::from_mode(0o755)).unwrap();
+
+        directory
+    }
+
+    /// Builds a request for the python compiler with the given path.
+    fn request(path: &Path) -> SandboxRequest<'_> {
+        SandboxRequest::builder()
+            .id("test")
+            .timeout(Duration::from_secs(5))
+            .path(path)
+            .source("print('hello')")
+            .compiler(COMPILERS[0])
+            .build()
+            .unwrap()
+    }
+
+    /// The command the given backend builds for the given request, preparing and then cleaning up
+    /// the path of the request.
+    fn command(backend: &DockerBackend, request: &SandboxRequest) -> Vec<String> {
+        let mut sandbox = Sandbox::new(request);
+        let paths = sandbox.prepare().unwrap();
+        sandbox.cleanup().unwrap();
+
+        backend.build_command(&paths, request).unwrap()
+    }
+
+    /// If the given arguments contain the given flag immediately followed by the given value.
+    fn has_flag(args: &[String], flag: &str, value: &str) -> bool {
+        args.windows(2).any(|pair| pair[0] == flag && pair[1] == value)
+    }
+
+    #[test]
+    fn compile_cache_is_hit_by_the_second_execution_and_never_mounted() {
+        let directory = fake_runtime(FAKE_RUNTIME);
+        let runtime = directory.join("runtime");
+        let compile_cache = directory.join("cache");
+
+        let backend = DockerBackend::new()
+            .with_runtime_binary(runtime.to_str().unwrap())
+            .with_image_pulling(false)
+            .with_compile_cache(&compile_cache);
+
+        let mut hits = Vec::new();
+
+        for _ in 0..2 {
+            let path = SandboxRequest::temp_path("cache").unwrap();
+            let request = SandboxRequest::builder()
+                .id("cache")
+                .timeout(Duration::from_secs(5))
+                .path(&path)
+                .source("int main() {}")
+                .compiler(COMPILERS[2])
+                .build()
+                .unwrap();
+
+            hits.push(Sandbox::new(&request).with_backend(&backend).run().unwrap().compile_cache_hit);
+        }
+
+        let log = fs::read_to_string(runtime.with_extension("log")).unwrap();
+        let runs: Vec<&str> = log.lines().filter(|line| line.starts_with("run ")).collect();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(hits, [false, true]);
+        assert_eq!(runs.len(), 3, "expected a compile and two run containers: {:?}", runs);
+        assert_eq!(runs.iter().filter(|line| line.contains(" compile")).count(), 1);
+        assert!(!log.contains(&compile_cache.display().to_string()), "the compile cache was mounted: {}", log);
+    }
+
+    #[test]
+    fn execution_env_gives_go_a_writable_home_and_build_cache() {
+        let path = SandboxRequest::temp_path("env").unwrap();
+        let env = [("HOME", "/home/user")];
+
+        let request = SandboxRequest::builder()
+            .id("env")
+            .timeout(Duration::from_secs(5))
+            .path(&path)
+            .source("package main")
+            .compiler(compiler_for("go").unwrap())
+            .env(&env)
+            .build()
+            .unwrap();
+
+        let variables: Vec<(&str, &str)> = execution_env(&request).collect();
+        assert!(variables.contains(&("GOCACHE", "/tmp/go-cache")));
+        assert!(variables.contains(&("HOME", "/home/user")));
+        assert!(!variables.contains(&("HOME", "/tmp")));
+        assert!(variables.contains(&("TZ", "UTC")));
+    }
+
+    #[test]
+    fn local_temp_path_replaces_only_the_temp_directory() {
+        let temp_directory = Path::new("/requests/test/tmp");
+
+        assert_eq!(local_temp_path("/tmp/cpp.o", temp_directory), "/requests/test/tmp/cpp.o");
+        assert_eq!(local_temp_path("/tmp", temp_directory), "/requests/test/tmp");
+        assert_eq!(local_temp_path("/tmpfile", temp_directory), "/tmpfile");
+        assert_eq!(local_temp_path("-cp", temp_directory), "-cp");
+    }
+
+    #[test]
+    fn local_backend_writes_the_compiled_output_within_the_request_path() {
+        // Copying the source code into /tmp stands in for a compiler writing its compiled output.
+        static LOCAL_TEMP: LanguageCompiler = LanguageCompiler {
+            language: "localtmp",
+            compiler: "cp",
+            interpreter: false,
+            additional_arguments: Some("-t /tmp"),
+            compiled_output: Some("sh /tmp/localtmp.sh"),
+            ..SHELL
+        };
+
+        let path = SandboxRequest::temp_path("local").unwrap();
+        let request = SandboxRequest::builder()
+            .id("local")
+            .timeout(Duration::from_secs(5))
+            .path(&path)
+            .source("echo compiled")
+            .compiler(registered(&LOCAL_TEMP))
+            .build()
+            .unwrap();
+
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stdout, "compiled\n");
+        assert!(!Path::new("/tmp/localtmp.sh").exists());
+    }
+
+    #[test]
+    fn run_watched_reports_the_status_of_a_completed_command() {
+        let watched = run_watched(&mut Command::new("true"), "true", "test", Duration::from_secs(5), None);
+
+        assert!(watched.status.unwrap().success());
+        assert!(!watched.timed_out && !watched.cancelled);
+        assert!(watched.finished_at >= watched.started_at);
+    }
+
+    #[test]
+    fn run_watched_marks_a_command_past_its_timeout_as_timed_out() {
+        let mut command = Command::new("sleep");
+        command.arg("0.5");
+
+        let watched = run_watched(&mut command, "true", "test", Duration::from_millis(10), None);
+        assert!(watched.timed_out && !watched.cancelled);
+    }
+
+    #[test]
+    fn run_watched_marks_a_cancelled_command_as_cancelled() {
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+
+        let mut command = Command::new("sleep");
+        command.arg("0.5");
+
+        let watched = run_watched(&mut command, "true", "test", Duration::from_secs(5), Some(&cancellation));
+        assert!(watched.cancelled && !watched.timed_out);
+    }
+
+    #[test]
+    fn container_args_limit_the_memory_and_the_swap() {
+        let unlimited = request(Path::new("temp/test"));
+        let args = container_args(&SandboxRequest { memory_limit: Some(64 * 1024 * 1024), ..unlimited });
+
+        assert!(has_flag(&args, "--memory", "67108864b"));
+        assert!(has_flag(&args, "--memory-swap", "67108864b"));
+        assert!(!container_args(&unlimited).iter().any(|arg| arg == "--memory"));
+    }
+
+    #[test]
+    fn container_args_limit_the_cpus() {
+        let unlimited = request(Path::new("temp/test"));
+        let args = container_args(&SandboxRequest { cpu_limit: Some(0.5), ..unlimited });
+
+        assert!(has_flag(&args, "--cpus", "0.5"));
+        assert!(!container_args(&unlimited).iter().any(|arg| arg == "--cpus"));
+    }
+
+    #[test]
+    fn container_args_disable_the_network_unless_enabled() {
+        let request = request(Path::new("temp/test"));
+
+        assert!(has_flag(&container_args(&request), "--network", "none"));
+        assert!(!container_args(&SandboxRequest { network_enabled: true, ..request }).iter().any(|arg| arg == "--network"));
+    }
+
+    #[test]
+    fn script_args_include_the_additional_arguments_of_the_compiler() {
+        let python = request(Path::new("temp/test"));
+        let cpp = SandboxRequest { compiler: compiler_for("cpp").unwrap(), ..python };
+
+        let args = script_args(&cpp, ScriptStage::All);
+        assert_eq!(args[..7], ["sh", "./script.sh", "g++", "cpp.cpp", "stdin", "/tmp/cpp.o", "-o /tmp/cpp.o"]);
+        assert_eq!(script_args(&python, ScriptStage::All)[6], "");
+    }
+
+    #[test]
+    fn run_args_pass_the_environment_variables_of_the_request() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let env = [("PYTHONHASHSEED", "0"), ("GREETING", "hello world")];
+        let with_env = SandboxRequest { env: &env, ..request(&path) };
+
+        let args = command(&DockerBackend::new(), &with_env);
+        assert!(has_flag(&args, "-e", "PYTHONHASHSEED=0"));
+        assert!(has_flag(&args, "-e", "GREETING=hello world"));
+    }
+
+    #[test]
+    fn podman_runtime_executes_the_podman_binary() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let python = request(&path);
+
+        assert_eq!(ContainerRuntime::default(), ContainerRuntime::Docker);
+        assert_eq!(ContainerRuntime::Podman.binary(), "podman");
+        assert_eq!(command(&DockerBackend::new(), &python)[..2], ["docker", "run"]);
+        assert_eq!(command(&DockerBackend::new().with_runtime(ContainerRuntime::Podman), &python)[..2], ["podman", "run"]);
+    }
+
+    #[test]
+    fn runtime_is_unavailable_when_not_installed_or_not_ready() {
+        assert!(check_runtime_available("true").is_ok());
+        assert!(matches!(check_runtime_available("false"), Err(SandboxError::RuntimeUnavailable(_))));
+
+        let missing = check_runtime_available("compileme-missing-runtime");
+        assert!(matches!(missing, Err(SandboxError::RuntimeUnavailable(reason)) if reason.contains("is not installed")));
+
+        let backend = DockerBackend::new().with_runtime_binary("compileme-missing-runtime");
+        assert!(matches!(backend.check_runtime_available(), Err(SandboxError::RuntimeUnavailable(_))));
+    }
+
+    #[test]
+    fn ensure_image_only_pulls_missing_images() {
+        let directory = fake_runtime(PULLING_RUNTIME);
+        let runtime = directory.join("runtime");
+        let binary = runtime.to_str().unwrap();
+
+        ensure_image(binary, "present").unwrap();
+        ensure_image(binary, "missing").unwrap();
+
+        let log = fs::read_to_string(runtime.with_extension("log")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let pulls: Vec<&str> = log.lines().filter(|line| line.starts_with("pull ")).collect();
+        assert_eq!(pulls, ["pull missing"]);
+    }
+
+    #[test]
+    fn container_name_is_unique_and_sanitized() {
+        let names: std::collections::HashSet<String> = (0..100).map(|_| container_name("test").unwrap()).collect();
+        assert_eq!(names.len(), 100);
+        assert!(names.iter().all(|name| name.starts_with("sandbox_test_")));
+
+        assert!(container_name("a b/c").unwrap().starts_with("sandbox_a_b_c_"));
+        assert!(matches!(container_name("../.."), Err(SandboxError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn runtime_binary_is_used_instead_of_the_binary_of_the_runtime() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let python = request(&path);
+
+        let backend = DockerBackend::new().with_runtime(ContainerRuntime::Podman).with_runtime_binary("/opt/docker/bin/docker");
+        assert_eq!(command(&backend, &python)[0], "/opt/docker/bin/docker");
+
+        let config = SandboxConfig { runtime_binary: Some("/opt/docker/bin/docker"), ..Default::default() };
+        let mut sandbox = Sandbox::with_config(&config, &python);
+        let paths = sandbox.prepare().unwrap();
+        let configured = sandbox.build_command(&paths).unwrap();
+        sandbox.cleanup().unwrap();
+
+        assert_eq!(configured[0], "/opt/docker/bin/docker");
+    }
+
+    #[test]
+    fn run_args_mount_the_request_path_read_only_except_the_output_files() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let python = request(&path);
+
+        let mut sandbox = Sandbox::new(&python);
+        let paths = sandbox.prepare().unwrap();
+        sandbox.cleanup().unwrap();
+
+        let args = DockerBackend::new().build_command(&paths, &python).unwrap();
+        let directory = paths.directory.display();
+
+        assert!(has_flag(&args, "-v", &format!("{}:/input:ro", directory)));
+        assert!(has_flag(&args, "-v", &format!("{}/python.out:/input/python.out", directory)));
+        assert!(has_flag(&args, "-v", &format!("{}/python.error.out:/input/python.error.out", directory)));
+        assert!(has_flag(&args, "-v", &format!("{}/status:/input/status", directory)));
+        assert!(!args.iter().any(|arg| arg.ends_with(&format!("{}:/input", directory))));
+    }
+
+    #[test]
+    fn container_args_run_as_the_nobody_user_by_default() {
+        let python = request(Path::new("temp/test"));
+
+        assert!(has_flag(&container_args(&python), "--user", "65534"));
+        assert!(has_flag(&container_args(&SandboxRequest { run_as_uid: Some(1000), ..python }), "--user", "1000"));
+    }
+
+    #[test]
+    fn container_args_apply_the_seccomp_profile() {
+        let python = request(Path::new("temp/test"));
+        let profile = Path::new("/etc/compileme/seccomp.json");
+
+        let args = container_args(&SandboxRequest { seccomp_profile: Some(profile), ..python });
+        assert!(has_flag(&args, "--security-opt", "seccomp=/etc/compileme/seccomp.json"));
+        assert!(!container_args(&python).iter().any(|arg| arg == "--security-opt"));
+    }
+
+    #[test]
+    fn missing_seccomp_profile_is_an_invalid_request() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let profile = path.join("missing.json");
+        let python = SandboxRequest { seccomp_profile: Some(&profile), ..request(&path) };
+
+        let result = Sandbox::new(&python).with_backend(MockBackend::new()).run();
+        assert!(matches!(result, Err(SandboxError::InvalidRequest(_))));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn container_logs_are_only_captured_when_enabled() {
+        let directory = fake_runtime(LOGGING_RUNTIME);
+        let runtime = directory.join("runtime");
+        let backend = DockerBackend::new().with_runtime_binary(runtime.to_str().unwrap()).with_image_pulling(false);
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let python = request(&path);
+
+        let captured = Sandbox::new(&python).with_backend(backend.clone().with_container_logs(true)).run().unwrap();
+        let discarded = Sandbox::new(&python).with_backend(backend).run().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(captured.container_logs.as_deref(), Some("container output\ncontainer error\n"));
+        assert_eq!(discarded.container_logs, None);
+    }
+
+    #[test]
+    fn local_backend_executes_the_code_within_the_request_path_with_its_env() {
+        let path = SandboxRequest::temp_path("local").unwrap();
+        let env = [("GREETING", "hello")];
+        let local = SandboxRequest { compiler: registered(&SHELL), source_code: "pwd; echo \"$GREETING $TZ\"", env: &env, ..request(&path) };
+
+        let response = Sandbox::new(&local).with_backend(LocalBackend::new()).run().unwrap();
+        let directory = fs::canonicalize(env::temp_dir()).unwrap().join(path.file_name().unwrap());
+
+        assert_eq!(response.stdout, format!("{}\nhello UTC\n", directory.display()));
+        assert_eq!(response.exit_code, Some(0));
+    }
+
+    #[test]
+    fn container_args_limit_the_processes() {
+        let python = request(Path::new("temp/test"));
+
+        assert!(has_flag(&container_args(&python), "--pids-limit", "128"));
+        assert!(has_flag(&container_args(&SandboxRequest { pids_limit: Some(16), ..python }), "--pids-limit", "16"));
+    }
+
+    #[test]
+    fn execution_env_defaults_the_locale_and_the_timezone() {
+        let python = request(Path::new("temp/test"));
+        let env = [("TZ", "Europe/London")];
+
+        let defaults: Vec<(&str, &str)> = execution_env(&python).collect();
+        assert_eq!(defaults, [("LANG", "C.UTF-8"), ("LC_ALL", "C.UTF-8"), ("TZ", "UTC")]);
+
+        let overridden = SandboxRequest { env: &env, ..python };
+        let variables: Vec<(&str, &str)> = execution_env(&overridden).collect();
+        assert!(variables.contains(&("TZ", "Europe/London")));
+        assert!(!variables.contains(&("TZ", "UTC")));
+        assert!(variables.contains(&("LANG", "C.UTF-8")));
+    }
+
+    #[test]
+    fn warm_backend_reuses_the_container_between_executions() {
+        let directory = fake_runtime(FAKE_RUNTIME);
+        let runtime = directory.join("runtime");
+        let docker = DockerBackend::new().with_runtime_binary(runtime.to_str().unwrap()).with_image_pulling(false);
+        let backend = WarmDockerBackend::new(docker).with_warm_containers(1);
+
+        for _ in 0..3 {
+            let path = SandboxRequest::temp_path("warm").unwrap();
+            Sandbox::new(&request(&path)).with_backend(&backend).run().unwrap();
+        }
+
+        assert_eq!(backend.idle_containers(), 1);
+        drop(backend);
+
+        let log = fs::read_to_string(runtime.with_extension("log")).unwrap();
+        let count = |prefix: &str| log.lines().filter(|line| line.starts_with(prefix)).count();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(count("run "), 1, "expected a single container to be started: {}", log);
+        assert_eq!(log.lines().filter(|line| line.starts_with("exec ") && line.contains("./script.sh")).count(), 3);
+        assert_eq!(count("rm -f "), 1, "expected the idle container to be removed once dropped: {}", log);
+    }
+
+    #[test]
+    fn warm_backend_removes_the_containers_over_the_warm_containers() {
+        let directory = fake_runtime(FAKE_RUNTIME);
+        let runtime = directory.join("runtime");
+        let docker = DockerBackend::new().with_runtime_binary(runtime.to_str().unwrap()).with_image_pulling(false);
+        let backend = WarmDockerBackend::new(docker).with_warm_containers(0);
+
+        let path = SandboxRequest::temp_path("warm").unwrap();
+        Sandbox::new(&request(&path)).with_backend(&backend).run().unwrap();
+
+        assert_eq!(backend.idle_containers(), 0);
+        let log = fs::read_to_string(runtime.with_extension("log")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(log.lines().filter(|line| line.starts_with("rm -f ")).count(), 1);
+    }
+
+    #[test]
+    fn compile_cache_key_changes_with_the_source_and_the_compiler() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let cpp = SandboxRequest { compiler: compiler_for("cpp").unwrap(), source_code: "int main() {}", ..request(&path) };
+        let optimized = LanguageCompiler { additional_arguments: Some("-O2"), ..*cpp.compiler };
+
+        assert_eq!(compile_cache_key(&cpp), compile_cache_key(&cpp));
+        assert_ne!(compile_cache_key(&cpp), compile_cache_key(&SandboxRequest { source_code: "int main() {  }", ..cpp }));
+        assert_ne!(compile_cache_key(&cpp), compile_cache_key(&SandboxRequest { compiler: &optimized, ..cpp }));
+        assert_ne!(compile_cache_key(&cpp), compile_cache_key(&SandboxRequest { compiler: compiler_for("rust").unwrap(), ..cpp }));
+    }
+
+    #[test]
+    fn runtime_image_compiles_and_runs_in_separate_containers() {
+        let directory = fake_runtime(FAKE_RUNTIME);
+        let runtime = directory.join("runtime");
+        let backend = DockerBackend::new().with_runtime_binary(runtime.to_str().unwrap()).with_image_pulling(false);
+
+        let cpp = compiler_for("cpp").unwrap();
+        let staged = LanguageCompiler { runtime_image: Some("cpp_runtime"), ..*cpp };
+
+        let path = SandboxRequest::temp_path("staged").unwrap();
+        let request = SandboxRequest { compiler: &staged, tmpfs_size: Some(1024 * 1024), ..request(&path) };
+        assert!(backend.is_staged(&request));
+        assert!(!backend.is_staged(&SandboxRequest { compiler: cpp, ..request }));
+
+        Sandbox::new(&request).with_backend(&backend).run().unwrap();
+
+        let log = fs::read_to_string(runtime.with_extension("log")).unwrap();
+        let runs: Vec<&str> = log.lines().filter(|line| line.starts_with("run ")).collect();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(runs.len(), 2, "expected a compile and a run container: {:?}", runs);
+        assert!(runs[0].contains(&format!(" {} ", cpp.virtual_machine_name)) && runs[0].contains(" compile"));
+        assert!(runs[1].contains(" cpp_runtime ") && !runs[1].contains(" compile"));
+        assert!(runs.iter().all(|run| !run.contains("--tmpfs")), "the tmpfs was mounted over the artifacts: {:?}", runs);
+    }
+
+    #[test]
+    fn pulling_a_bogus_image_is_image_not_found() {
+        let directory = fake_runtime(PULLING_RUNTIME);
+        let binary = directory.join("runtime");
+        let binary = binary.to_str().unwrap();
+
+        let unknown = ensure_image(binary, "unknown");
+        let broken = ensure_image(binary, "broken");
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(matches!(unknown, Err(SandboxError::ImageNotFound(image)) if image == "unknown"));
+        assert!(matches!(broken, Err(SandboxError::ContainerFailed(1))));
+    }
+
+    #[test]
+    fn running_a_bogus_image_without_pulling_is_image_not_found() {
+        let directory = fake_runtime("#!/bin/sh\n[ \"$1\" = \"run\" ] && exit 125\nexit 1\n");
+        let runtime = directory.join("runtime");
+        let backend = DockerBackend::new().with_runtime_binary(runtime.to_str().unwrap()).with_image_pulling(false);
+
+        let bogus = LanguageCompiler { virtual_machine_name: "bogus_virtual_machine", ..*COMPILERS[0] };
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let python = SandboxRequest { compiler: &bogus, ..request(&path) };
+
+        let result = Sandbox::new(&python).with_backend(&backend).run();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(matches!(result, Err(SandboxError::ImageNotFound(image)) if image == "bogus_virtual_machine"));
+    }
+
+    #[test]
+    fn wait_bounded_kills_a_command_past_its_timeout() {
+        let started = Instant::now();
+        let status = wait_bounded(Command::new("sleep").arg("5"), Duration::from_millis(100)).unwrap();
+
+        assert!(!status.success());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(wait_bounded(&mut Command::new("true"), Duration::from_secs(5)).unwrap().success());
+    }
+
+    #[test]
+    fn script_args_end_with_the_program_args() {
+        let program_args = ["hello world", "second"];
+        let python = SandboxRequest { program_args: &program_args, ..request(Path::new("temp/test")) };
+        let args = script_args(&python, ScriptStage::All);
+
+        assert_eq!(&args[args.len() - 2..], ["hello world", "second"]);
+        let without_args = script_args(&SandboxRequest { program_args: &[], ..python }, ScriptStage::All);
+        assert_eq!(without_args.len(), args.len() - 2);
+    }
+
+    #[test]
+    fn script_passes_the_program_args_to_the_executed_code() {
+        let program_args = ["hello world"];
+
+        let path = SandboxRequest::temp_path("args").unwrap();
+        let source = "echo \"$1\"";
+        let echoing = SandboxRequest { compiler: registered(&SHELL), source_code: source, program_args: &program_args, ..request(&path) };
+
+        let mut sandbox = Sandbox::new(&echoing);
+        let paths = sandbox.prepare().unwrap();
+        let args = script_args(&echoing, ScriptStage::All);
+
+        let status = Command::new(&args[0]).args(&args[1..]).current_dir(&paths.directory).status().unwrap();
+        let stdout = fs::read_to_string(&paths.stdout_file).unwrap();
+        sandbox.cleanup().unwrap();
+
+        assert!(status.success());
+        assert!(stdout.starts_with("hello world\n*-COMPILE::EOF-*"), "unexpected output: {:?}", stdout);
+    }
+
+    #[test]
+    fn injected_id_and_program_args_are_treated_as_data() {
+        let program_args = ["; touch injected", "$(touch injected)"];
+
+        let path = SandboxRequest::temp_path("args").unwrap();
+        let source = "printf '%s\\n' \"$@\"";
+        let injecting = SandboxRequest {
+            id: "x; rm -rf ~",
+            compiler: registered(&SHELL),
+            source_code: source,
+            program_args: &program_args,
+            ..request(&path)
+        };
+
+        let args = command(&DockerBackend::new(), &injecting);
+        assert!(args.iter().any(|arg| arg == "; touch injected") && args.iter().any(|arg| arg == "$(touch injected)"));
+        assert!(args.iter().all(|arg| !arg.contains("x; rm")), "the id was not sanitized: {:?}", args);
+
+        let mut sandbox = Sandbox::new(&injecting);
+        let paths = sandbox.prepare().unwrap();
+        let args = script_args(&injecting, ScriptStage::All);
+
+        Command::new(&args[0]).args(&args[1..]).current_dir(&paths.directory).status().unwrap();
+        let stdout = fs::read_to_string(&paths.stdout_file).unwrap();
+        let injected = paths.directory.join("injected").exists();
+        sandbox.cleanup().unwrap();
+
+        assert!(stdout.starts_with("; touch injected\n$(touch injected)\n-\n"), "unexpected output: {:?}", stdout);
+        assert!(!injected, "the program args were executed by the shell");
+    }
+
+    #[test]
+    fn container_args_mount_a_tmpfs_only_when_sized() {
+        let python = request(Path::new("temp/test"));
+        let args = container_args(&SandboxRequest { tmpfs_size: Some(64 * 1024 * 1024), ..python });
+
+        assert!(has_flag(&args, "--tmpfs", "/tmp:rw,exec,nosuid,size=67108864"));
+        assert!(!container_args(&python).iter().any(|arg| arg == "--tmpfs"));
+    }
+}
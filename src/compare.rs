@@ -0,0 +1,469 @@
+use std::fmt;
+
+/// The mode used to compare the actual output of executed code against the expected output of a
+/// test. The stricter modes are useful when the exact formatting of the output is part of the
+/// answer.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub enum ComparisonMode {
+    /// Each line of the output must exactly match each line of the expected output.
+    #[default]
+    Exact,
+    /// Trailing whitespace at the end of each line and trailing blank lines at the end of the
+    /// output are ignored, all other whitespace must match.
+    TrimTrailingWhitespace,
+    /// Each line is trimmed and all runs of whitespace within the line are collapsed to a single
+    /// space, blank lines are ignored entirely.
+    Normalized,
+    /// Each line is normalized and compared token by token, tokens which are both numbers are
+    /// compared within the given tolerance rather than exactly. This is used for floating point
+    /// answers which can differ in the last digits, e.g 3.14159 and 3.1416.
+    Numeric(Tolerance),
+}
+
+/// A custom comparator which decides if the actual output of executed code is accepted for the
+/// expected output of a test, this is used instead of the comparison mode of the test when the
+/// built in modes cannot describe every accepted output (e.g any valid ordering of the output).
+///
+/// # Example
+/// ```
+/// # use compileme::testing::MockBackend;
+/// # use compileme::compare::OutputComparator;
+/// # use compileme::sandbox::{self, Sandbox, SandboxRequest, SandboxTest, SandboxTestResult};
+/// # use std::time::Duration;
+/// /// Accepts any permutation of the expected tokens.
+/// struct AnyPermutation;
+///
+/// impl OutputComparator for AnyPermutation {
+///     fn compare(&self, expected: &str, actual: &str) -> bool {
+///         let mut expected: Vec<&str> = expected.split_whitespace().collect();
+///         let mut actual: Vec<&str> = actual.split_whitespace().collect();
+///
+///         expected.sort_unstable();
+///         actual.sort_unstable();
+///         expected == actual
+///     }
+/// }
+///
+/// let expected = vec!["1 2 3"];
+///
+/// let tests = [SandboxTest {
+///     expected_stdout_data: Some(&expected),
+///     comparator: Some(&AnyPermutation),
+///     ..SandboxTest::new("permutation")
+/// }];
+///
+/// let path = SandboxRequest::temp_path("permutation").unwrap();
+///
+/// let request = SandboxRequest::builder()
+///     .id("permutation")
+///     .timeout(Duration::from_secs(20))
+///     .path(&path)
+///     .source("print('3 1 2')")
+///     .compiler(sandbox::compiler_for("python").unwrap())
+///     .tests(&tests)
+///     .build()
+///     .unwrap();
+///
+/// let backend = MockBackend::new().with_stdout("3 1 2\n");
+/// let response = Sandbox::new(&request).with_backend(&backend).run().unwrap();
+/// assert_eq!(response.test_results[0].result, SandboxTestResult::Passed);
+///
+/// let backend = MockBackend::new().with_stdout("3 1 1\n");
+/// let response = Sandbox::new(&request).with_backend(&backend).run().unwrap();
+/// assert_eq!(response.test_results[0].result, SandboxTestResult::Failed);
+/// ```
+pub trait OutputComparator: Sync {
+    /// Returns if the given actual output is accepted for the given expected output. Both outputs
+    /// are given in the same form, the lines of the output joined by a line feed without a final
+    /// line feed. Any carriage returns ending the lines of the actual output are removed, so output
+    /// written with windows line endings is given the same as any other.
+    ///
+    /// # Arguments
+    /// * `expected` - The expected output, the expected lines of the test joined by new lines.
+    /// * `actual` - The actual output of the executed code, the lines joined by new lines.
+    fn compare(&self, expected: &str, actual: &str) -> bool;
+}
+
+/// Comparators cannot be compared themselves, so two comparators are only equal when they are the
+/// same comparator.
+impl PartialEq for dyn OutputComparator + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+impl fmt::Debug for dyn OutputComparator + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OutputComparator")
+    }
+}
+
+/// The tolerance within which two numbers are treated as equal, two numbers are equal when the
+/// difference is within the absolute tolerance or within the relative tolerance of the expected
+/// number.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Tolerance {
+    /// The max absolute difference between the expected and actual number.
+    pub absolute: f64,
+    /// The max difference between the expected and actual number relative to the expected number.
+    pub relative: f64,
+}
+
+impl Tolerance {
+    /// Returns if the given actual number is within the tolerance of the given expected number.
+    ///
+    /// # Arguments
+    /// * `expected` - The expected number.
+    /// * `actual` - The actual number.
+    pub fn within(&self, expected: f64, actual: f64) -> bool {
+        let difference = (expected - actual).abs();
+        difference <= self.absolute || difference <= self.relative * expected.abs()
+    }
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance { absolute: 1e-6, relative: 1e-6 }
+    }
+}
+
+impl ComparisonMode {
+    /// Normalizes the given lines of output based on the comparison mode, two outputs are treated
+    /// as matching when their normalized lines are equal.
+    ///
+    /// # Arguments
+    /// * `lines` - The lines of the output being normalized.
+    pub fn normalize<'a>(&self, lines: impl Iterator<Item = &'a str>) -> Vec<String> {
+        match self {
+            ComparisonMode::Exact => lines.map(str::to_string).collect(),
+            ComparisonMode::TrimTrailingWhitespace => {
+                let mut lines: Vec<String> = lines.map(|line| line.trim_end().to_string()).collect();
+
+                while lines.last().is_some_and(|line| line.is_empty()) {
+                    lines.pop();
+                }
+
+                lines
+            }
+            ComparisonMode::Normalized | ComparisonMode::Numeric(_) => lines
+                .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+                .filter(|line| !line.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Compares the given actual output against the given expected lines of output, returning if
+    /// the output matches based on the comparison mode. When case insensitive the letter case of
+    /// the normalized lines is ignored. In every mode the lines of the actual output can end with
+    /// either a line feed or a carriage return and line feed (CRLF), so output written with windows
+    /// line endings matches expected output written without them.
+    ///
+    /// # Arguments
+    /// * `expected` - The expected lines of output.
+    /// * `actual` - The actual output of the executed code.
+    /// * `case_insensitive` - If the letter case of the output should be ignored.
+    pub fn matches(&self, expected: &[&str], actual: &str, case_insensitive: bool) -> bool {
+        self.first_mismatch(expected, actual, case_insensitive).is_none()
+    }
+
+    /// Finds the index (from zero) of the first line at which the given actual output does not
+    /// match the given expected lines of output, or at which one of the outputs ran out of lines.
+    /// The index is of the normalized lines, so blank lines ignored by the comparison mode are not
+    /// counted. Returns none when the output matches.
+    ///
+    /// # Arguments
+    /// * `expected` - The expected lines of output.
+    /// * `actual` - The actual output of the executed code.
+    /// * `case_insensitive` - If the letter case of the output should be ignored.
+    pub fn first_mismatch(&self, expected: &[&str], actual: &str, case_insensitive: bool) -> Option<usize> {
+        let expected = self.normalize(expected.iter().copied());
+        let actual = self.normalize(actual.lines());
+
+        (0..expected.len().max(actual.len())).find(|&index| match (expected.get(index), actual.get(index)) {
+            (Some(expected), Some(actual)) => !self.lines_match(expected, actual, case_insensitive),
+            _ => true,
+        })
+    }
+
+    /// Compares a single normalized expected line against a single normalized actual line, when
+    /// comparing numerically each of the tokens of the lines are compared.
+    ///
+    /// # Arguments
+    /// * `expected` - The normalized expected line.
+    /// * `actual` - The normalized actual line.
+    /// * `case_insensitive` - If the letter case of the lines should be ignored.
+    fn lines_match(&self, expected: &str, actual: &str, case_insensitive: bool) -> bool {
+        match self {
+            ComparisonMode::Numeric(tolerance) => {
+                let expected: Vec<&str> = expected.split(' ').collect();
+                let actual: Vec<&str> = actual.split(' ').collect();
+
+                expected.len() == actual.len() && expected.iter().zip(&actual)
+                    .all(|(expected, actual)| tokens_match(expected, actual, tolerance, case_insensitive))
+            }
+            _ if case_insensitive => expected.to_lowercase() == actual.to_lowercase(),
+            _ => expected == actual,
+        }
+    }
+}
+
+/// Compares the given expected token against the given actual token, when both tokens are numbers
+/// they are compared within the given tolerance, otherwise they must be equal.
+///
+/// # Arguments
+/// * `expected` - The expected token.
+/// * `actual` - The actual token.
+/// * `tolerance` - The tolerance within which numbers are equal.
+/// * `case_insensitive` - If the letter case of tokens which are not numbers should be ignored.
+fn tokens_match(expected: &str, actual: &str, tolerance: &Tolerance, case_insensitive: bool) -> bool {
+    match (expected.parse::<f64>(), actual.parse::<f64>()) {
+        (Ok(expected), Ok(actual)) if !expected.is_nan() && !actual.is_nan() => tolerance.within(expected, actual),
+        _ if case_insensitive => expected.to_lowercase() == actual.to_lowercase(),
+        _ => expected == actual,
+    }
+}
+
+/// The max number of pairs of differing lines that are diffed line by line, larger differences
+/// (after removing the lines common to the start and end of both outputs) are reported as every
+/// remaining expected line removed and every remaining actual line added. This bounds the time
+/// taken to diff the output of code which printed far more than expected.
+const MAX_DIFF_CELLS: usize = 16 * 1024 * 1024;
+
+/// Produces a line oriented diff between the expected and actual lines of output, each line of the
+/// diff is prefixed with a space when in both outputs, a minus when only in the expected output and
+/// a plus when only in the actual output.
+///
+/// The longest common subsequence of the lines is found with the Hirschberg algorithm, so the
+/// memory used is linear in the number of lines rather than the product of both.
+///
+/// # Arguments
+/// * `expected` - The expected lines of output.
+/// * `actual` - The actual lines of output.
+pub fn line_diff(expected: &[&str], actual: &[&str]) -> String {
+    let mut diff = String::new();
+
+    // The lines common to the start and the end of both outputs are always part of the diff
+    // unchanged, which leaves only the differing middle of the outputs to be diffed.
+    let prefix = expected.iter().zip(actual).take_while(|(expected, actual)| expected == actual).count();
+    let suffix = expected[prefix..].iter().rev().zip(actual[prefix..].iter().rev())
+        .take_while(|(expected, actual)| expected == actual)
+        .count();
+
+    let (remaining_expected, remaining_actual) = (&expected[prefix..expected.len() - suffix], &actual[prefix..actual.len() - suffix]);
+
+    push_lines(&mut diff, ' ', &expected[..prefix]);
+
+    if remaining_expected.len().saturating_mul(remaining_actual.len()) > MAX_DIFF_CELLS {
+        push_lines(&mut diff, '-', remaining_expected);
+        push_lines(&mut diff, '+', remaining_actual);
+    } else {
+        diff_lines(&mut diff, remaining_expected, remaining_actual);
+    }
+
+    push_lines(&mut diff, ' ', &expected[expected.len() - suffix..]);
+    diff
+}
+
+/// Appends the diff between the given expected and actual lines to the given diff, recursively
+/// splitting the expected lines in half at the point where the longest common subsequence of the
+/// lines crosses the middle.
+///
+/// # Arguments
+/// * `diff` - The diff being produced.
+/// * `expected` - The expected lines of output.
+/// * `actual` - The actual lines of output.
+fn diff_lines(diff: &mut String, expected: &[&str], actual: &[&str]) {
+    match expected {
+        [] => push_lines(diff, '+', actual),
+        _ if actual.is_empty() => push_lines(diff, '-', expected),
+        [line] => match actual.iter().position(|actual| actual == line) {
+            Some(index) => {
+                push_lines(diff, '+', &actual[..index]);
+                push_lines(diff, ' ', &actual[index..=index]);
+                push_lines(diff, '+', &actual[index + 1..]);
+            }
+            None => {
+                push_lines(diff, '-', expected);
+                push_lines(diff, '+', actual);
+            }
+        },
+        _ => {
+            let middle = expected.len() / 2;
+            let forward = common_lengths(expected[..middle].iter(), actual.iter());
+            let backward = common_lengths(expected[middle..].iter().rev(), actual.iter().rev());
+
+            // The actual lines are split where the common subsequence of the first half of the
+            // expected lines and the second half together is the longest.
+            let split = (0..=actual.len())
+                .max_by_key(|&index| (forward[index] + backward[actual.len() - index], std::cmp::Reverse(index)))
+                .unwrap_or(0);
+
+            diff_lines(diff, &expected[..middle], &actual[..split]);
+            diff_lines(diff, &expected[middle..], &actual[split..]);
+        }
+    }
+}
+
+/// The length of the longest common subsequence of the given expected lines and each prefix of the
+/// given actual lines, only a single row of the table is kept at a time.
+///
+/// # Arguments
+/// * `expected` - The expected lines of output.
+/// * `actual` - The actual lines of output.
+fn common_lengths<'a>(expected: impl Iterator<Item = &'a &'a str>, actual: impl Iterator<Item = &'a &'a str> + Clone) -> Vec<usize> {
+    let mut row = vec![0usize; actual.clone().count() + 1];
+
+    for expected in expected {
+        let mut diagonal = 0;
+
+        for (index, actual) in actual.clone().enumerate() {
+            let above = row[index + 1];
+
+            row[index + 1] = if expected == actual {
+                diagonal + 1
+            } else {
+                above.max(row[index])
+            };
+
+            diagonal = above;
+        }
+    }
+
+    row
+}
+
+/// Appends each of the given lines to the given diff with the given prefix.
+///
+/// # Arguments
+/// * `diff` - The diff being produced.
+/// * `prefix` - The prefix of each line.
+/// * `lines` - The lines being appended.
+fn push_lines(diff: &mut String, prefix: char, lines: &[&str]) {
+    for line in lines {
+        diff.push(prefix);
+        diff.push_str(line);
+        diff.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_diff_points_at_the_first_differing_line() {
+        let diff = line_diff(&["1", "2", "3", "4"], &["1", "2", "x", "4"]);
+        assert_eq!(diff, " 1\n 2\n-3\n+x\n 4\n");
+    }
+
+    #[test]
+    fn line_diff_reports_missing_and_extra_lines() {
+        assert_eq!(line_diff(&["a", "b", "c"], &["a", "c"]), " a\n-b\n c\n");
+        assert_eq!(line_diff(&["a", "c"], &["a", "b", "c"]), " a\n+b\n c\n");
+        assert_eq!(line_diff(&[], &["a"]), "+a\n");
+        assert_eq!(line_diff(&["a"], &[]), "-a\n");
+    }
+
+    #[test]
+    fn line_diff_keeps_the_longest_common_lines() {
+        let expected = ["a", "b", "c", "d", "e", "f"];
+        let actual = ["x", "b", "c", "y", "e", "z"];
+        let diff = line_diff(&expected, &actual);
+
+        let common: Vec<&str> = diff.lines().filter_map(|line| line.strip_prefix(' ')).collect();
+        assert_eq!(common, ["b", "c", "e"]);
+        assert_eq!(diff.lines().filter(|line| line.starts_with('-')).count(), 3);
+        assert_eq!(diff.lines().filter(|line| line.starts_with('+')).count(), 3);
+    }
+
+    #[test]
+    fn line_diff_of_large_outputs_starts_at_the_first_differing_line() {
+        let expected: Vec<String> = (0..30_000).map(|line| line.to_string()).collect();
+        let actual: Vec<String> = (0..30_000).map(|line| if line < 10 { line.to_string() } else { format!("x{}", line) }).collect();
+
+        let expected: Vec<&str> = expected.iter().map(String::as_str).collect();
+        let actual: Vec<&str> = actual.iter().map(String::as_str).collect();
+        let diff = line_diff(&expected, &actual);
+
+        assert_eq!(diff.lines().nth(9), Some(" 9"));
+        assert_eq!(diff.lines().nth(10), Some("-10"));
+        assert_eq!(diff.lines().count(), 10 + 2 * 29_990);
+    }
+
+    #[test]
+    fn normalized_mode_ignores_differences_in_whitespace() {
+        let expected = ["1 2  3", "done"];
+
+        assert!(ComparisonMode::Normalized.matches(&expected, "  1   2 3\t\n\n\ndone   \n\n", false));
+        assert!(!ComparisonMode::Normalized.matches(&expected, "1 2 3 done\n", false));
+        assert!(!ComparisonMode::Exact.matches(&expected, "1 2 3\ndone\n", false));
+    }
+
+    #[test]
+    fn trim_trailing_whitespace_mode_only_ignores_the_end_of_lines_and_output() {
+        let expected = ["a b", "", "c"];
+        let mode = ComparisonMode::TrimTrailingWhitespace;
+
+        assert!(mode.matches(&expected, "a b  \n\t\nc\n\n\n", false));
+        assert!(!mode.matches(&expected, " a b\n\nc\n", false));
+        assert!(!mode.matches(&expected, "a b\nc\n", false));
+    }
+
+    #[test]
+    fn case_insensitive_comparison_ignores_the_letter_case() {
+        let expected = ["Yes", "NO"];
+
+        assert!(ComparisonMode::Exact.matches(&expected, "YES\nno\n", true));
+        assert!(!ComparisonMode::Exact.matches(&expected, "YES\nno\n", false));
+        assert!(ComparisonMode::Normalized.matches(&expected, " yes \nNo\n", true));
+        assert!(ComparisonMode::Numeric(Tolerance::default()).matches(&["Pi 3.14"], "pi 3.14\n", true));
+        assert!(!ComparisonMode::Exact.matches(&expected, "yes\nnope\n", true));
+    }
+
+    #[test]
+    fn tolerance_accepts_an_absolute_or_relative_difference() {
+        let tolerance = Tolerance { absolute: 0.01, relative: 0.001 };
+
+        assert!(tolerance.within(1.23456, 1.2346));
+        assert!(tolerance.within(10_000.0, 10_005.0));
+        assert!(!tolerance.within(1.0, 1.02));
+        assert!(!tolerance.within(10_000.0, 10_011.0));
+    }
+
+    #[test]
+    fn numeric_mode_compares_numbers_within_the_tolerance() {
+        let mode = ComparisonMode::Numeric(Tolerance { absolute: 1e-3, relative: 0.0 });
+
+        assert!(mode.matches(&["area 3.14159", "1e3"], "area   3.1416\n1000\n", false));
+        assert!(!mode.matches(&["area 3.14159"], "area 3.15\n", false));
+        assert!(!mode.matches(&["area 3.14159"], "volume 3.14159\n", false));
+        assert!(!mode.matches(&["3.14159"], "3.14159 2\n", false));
+        assert!(mode.matches(&["NaN"], "NaN\n", false));
+        assert!(!mode.matches(&["NaN"], "nan\n", false));
+    }
+
+    #[test]
+    fn first_mismatch_is_the_index_of_the_first_differing_line() {
+        let expected = ["a", "b", "c"];
+
+        assert_eq!(ComparisonMode::Exact.first_mismatch(&expected, "a\nb\nc\n", false), None);
+        assert_eq!(ComparisonMode::Exact.first_mismatch(&expected, "a\nx\nc\n", false), Some(1));
+        assert_eq!(ComparisonMode::Exact.first_mismatch(&expected, "a\nb\n", false), Some(2));
+        assert_eq!(ComparisonMode::Exact.first_mismatch(&expected, "a\nb\nc\nd\n", false), Some(3));
+        assert_eq!(ComparisonMode::Normalized.first_mismatch(&expected, "a\n\n\nb\nx\n", false), Some(2));
+    }
+
+    #[test]
+    fn crlf_output_matches_lf_expected_output_in_every_mode() {
+        let modes = [
+            ComparisonMode::Exact,
+            ComparisonMode::TrimTrailingWhitespace,
+            ComparisonMode::Normalized,
+            ComparisonMode::Numeric(Tolerance::default()),
+        ];
+
+        for mode in &modes {
+            assert!(mode.matches(&["hello", "world"], "hello\r\nworld\r\n", false));
+        }
+    }
+}
@@ -0,0 +1,8 @@
+pub mod backend;
+pub mod compare;
+pub mod pool;
+pub mod registry;
+pub mod sandbox;
+#[cfg(any(test, feature = "mock"))]
+pub mod testing;
+pub mod usage;
@@ -1,17 +1,333 @@
-use crate::sandbox::SandboxRequest;
-use std::path::Path;
+use compileme::backend::LocalBackend;
+use compileme::registry::{default_registry, CompilerRegistry};
+use compileme::sandbox;
+use compileme::sandbox::{SandboxError, SandboxRequest, SandboxResponse, SandboxTest, TermReason};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{env, fs, process};
 
-pub mod sandbox;
+/// The usage of the command line interface, printed when the given arguments are not valid.
+const USAGE: &str = "usage: compileme --language <language> --source-file <path> [--timeout <seconds>] [--stdin-file <path>] [--local]
+       compileme list-languages";
+
+/// The number of seconds the executed code is allowed to run for when no timeout is given.
+const DEFAULT_TIMEOUT_SECONDS: u64 = 20;
+
+/// The exit code of the command line interface when the given arguments are not valid.
+const USAGE_EXIT_CODE: i32 = 2;
+
+/// The options of the command line interface, parsed from the arguments of the process.
+#[derive(Debug)]
+struct Options {
+    /// The language of the source code, used to find the compiler that executes the code.
+    language: String,
+    /// The path of the file containing the source code that will be executed.
+    source_file: PathBuf,
+    /// The max amount of time the executed code is allowed to run for.
+    timeout: Duration,
+    /// The path of the file containing the standard input of the executed code, if any.
+    stdin_file: Option<PathBuf>,
+    /// If the code is executed directly on the host with the local backend rather than within a
+    /// container, this provides no isolation and is only for trusted code.
+    local: bool,
+}
+
+impl Options {
+    /// Parses the options from the given arguments (not including the name of the program),
+    /// returning a description of the problem when the arguments are not valid.
+    ///
+    /// # Arguments
+    /// * `args` - The arguments that are being parsed.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut language = None;
+        let mut source_file = None;
+        let mut timeout = Duration::from_secs(DEFAULT_TIMEOUT_SECONDS);
+        let mut stdin_file = None;
+        let mut local = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+
+            match arg.as_str() {
+                "--language" => language = Some(value()?),
+                "--source-file" => source_file = Some(PathBuf::from(value()?)),
+                "--stdin-file" => stdin_file = Some(PathBuf::from(value()?)),
+                "--local" => local = true,
+                "--timeout" => {
+                    let seconds = value()?;
+                    let seconds = seconds.parse().map_err(|_| format!("invalid timeout {}", seconds))?;
+                    timeout = Duration::from_secs(seconds);
+                }
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+
+        Ok(Options {
+            language: language.ok_or("missing required argument --language")?,
+            source_file: source_file.ok_or("missing required argument --source-file")?,
+            timeout,
+            stdin_file,
+            local,
+        })
+    }
+}
+
+/// Runs the source code of the given options within the sandbox, the source code and the standard
+/// input are read from their files and the code is executed within a unique temp directory.
+///
+/// # Arguments
+/// * `options` - The parsed options of the command line interface.
+fn run(options: &Options) -> Result<SandboxResponse, SandboxError> {
+    let compiler = sandbox::compiler_for(&options.language)
+        .ok_or_else(|| SandboxError::InvalidRequest(format!("unsupported language {}", options.language)))?;
+
+    let source_code = fs::read_to_string(&options.source_file)?;
+
+    let stdin = match &options.stdin_file {
+        Some(stdin_file) => Some(fs::read_to_string(stdin_file)?),
+        None => None,
+    };
+
+    let stdin_data: Option<Vec<&str>> = stdin.as_deref().map(|stdin| stdin.lines().collect());
+
+    // The standard input is provided as a single test without any expected output, the test is
+    // only used to direct the standard input into the executed code.
+    let tests: Vec<SandboxTest> = stdin_data.iter()
+        .map(|stdin_data| SandboxTest { stdin_data: Some(stdin_data), ..SandboxTest::new("stdin") })
+        .collect();
+
+    let path = SandboxRequest::temp_path("cli")?;
+
+    let request = SandboxRequest::builder()
+        .id("cli")
+        .timeout(options.timeout)
+        .path(&path)
+        .source(&source_code)
+        .compiler(compiler)
+        .tests(&tests)
+        .build()?;
+
+    let mut sandbox = sandbox::Sandbox::new(&request);
+
+    if options.local {
+        sandbox = sandbox.with_backend(LocalBackend::new());
+    }
+
+    sandbox.run()
+}
+
+/// The exit code of the command line interface for the given response, this is the exit code of
+/// the executed code when it exited by itself and otherwise reflects how the code was terminated.
+///
+/// # Arguments
+/// * `response` - The response of the executed code.
+fn exit_code(response: &SandboxResponse) -> i32 {
+    match response.term_reason {
+        TermReason::Exited(code) => code,
+        TermReason::Signaled(signal) => 128 + signal,
+        TermReason::TimedOut | TermReason::OutOfMemory | TermReason::Cancelled => 1,
+    }
+}
+
+/// Writes the result of each of the tests of the given response on its own line, followed by how
+/// the executed code ended (e.g the exit code of the code or that the code timed out).
+///
+/// # Arguments
+/// * `response` - The response of the executed code.
+/// * `output` - The output the summary is written to.
+fn write_summary(response: &SandboxResponse, output: &mut impl Write) -> io::Result<()> {
+    for test in &response.test_results {
+        writeln!(output, "test {}: {}", test.id, test.result)?;
+    }
+
+    match response.term_reason {
+        TermReason::Exited(code) => writeln!(output, "exited with code {}", code),
+        TermReason::Signaled(signal) => writeln!(output, "terminated by signal {}", signal),
+        TermReason::TimedOut => writeln!(output, "{}", SandboxError::Timeout),
+        TermReason::OutOfMemory => writeln!(output, "exceeded the memory limit"),
+        TermReason::Cancelled => writeln!(output, "{}", SandboxError::Cancelled),
+    }
+}
+
+/// Writes each of the compilers of the given registry on its own line, with the language of the
+/// compiler and the name of the image the compiler is executed within.
+///
+/// # Arguments
+/// * `registry` - The registry of the compilers being listed.
+/// * `output` - The output the compilers are written to.
+fn list_languages(registry: &CompilerRegistry, output: &mut impl Write) -> io::Result<()> {
+    for language in registry.languages() {
+        if let Some(compiler) = registry.get(language) {
+            writeln!(output, "{}\t{}", compiler.language, compiler.virtual_machine_name)?;
+        }
+    }
+
+    Ok(())
+}
 
 fn main() {
-    println!("Hello, world!");
-
-    let sandbox = sandbox::Sandbox::new(&SandboxRequest {
-        id: "1234",
-        timeout: 20,
-        path: Path::new("./temp/random/python_test/"),
-        source_code: &"print('hello')".split_whitespace().collect::<Vec<&str>>(),
-        compiler: &sandbox::COMPILERS[0],
-        test: None,
-    });
+    if env::args().nth(1).as_deref() == Some("list-languages") {
+        if let Err(error) = list_languages(&default_registry(), &mut io::stdout()) {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            process::exit(USAGE_EXIT_CODE);
+        }
+    };
+
+    match run(&options) {
+        Ok(response) => {
+            print!("{}", response.stdout);
+            eprint!("{}", response.stderr);
+
+            if let Err(error) = write_summary(&response, &mut io::stderr()) {
+                eprintln!("{}", error);
+            }
+
+            process::exit(exit_code(&response));
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compileme::testing::{MockBackend, DART};
+    use compileme::registry::register_compiler;
+
+    /// Parses the options from the given arguments.
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn options_are_parsed_from_the_arguments() {
+        let options = parse(&["--language", "python", "--source-file", "main.py", "--timeout", "5", "--stdin-file", "in.txt", "--local"]).unwrap();
+
+        assert_eq!(options.language, "python");
+        assert_eq!(options.source_file, PathBuf::from("main.py"));
+        assert_eq!(options.timeout, Duration::from_secs(5));
+        assert_eq!(options.stdin_file, Some(PathBuf::from("in.txt")));
+        assert!(options.local);
+
+        let options = parse(&["--source-file", "main.py", "--language", "python"]).unwrap();
+        assert_eq!(options.timeout, Duration::from_secs(DEFAULT_TIMEOUT_SECONDS));
+        assert_eq!(options.stdin_file, None);
+        assert!(!options.local);
+    }
+
+    #[test]
+    fn invalid_arguments_are_described() {
+        assert_eq!(parse(&["--language", "python"]).unwrap_err(), "missing required argument --source-file");
+        assert_eq!(parse(&["--source-file", "main.py"]).unwrap_err(), "missing required argument --language");
+        assert_eq!(parse(&["--language"]).unwrap_err(), "missing value for --language");
+        assert_eq!(parse(&["--timeout", "soon"]).unwrap_err(), "invalid timeout soon");
+        assert_eq!(parse(&["--verbose"]).unwrap_err(), "unknown argument --verbose");
+    }
+
+    #[test]
+    fn unsupported_language_is_an_invalid_request() {
+        let options = parse(&["--language", "cobol", "--source-file", "main.cob"]).unwrap();
+        assert!(matches!(run(&options), Err(SandboxError::InvalidRequest(reason)) if reason == "unsupported language cobol"));
+    }
+
+    #[test]
+    fn exit_code_reflects_how_the_code_ended() {
+        let path = SandboxRequest::temp_path("cli").unwrap();
+        let request = SandboxRequest::builder()
+            .id("cli")
+            .timeout(Duration::from_secs(5))
+            .path(&path)
+            .source("print('hello')")
+            .compiler(sandbox::compiler_for("python").unwrap())
+            .build()
+            .unwrap();
+
+        let response = sandbox::Sandbox::new(&request).with_backend(MockBackend::new()).run().unwrap();
+        let with_term_reason = |term_reason| exit_code(&SandboxResponse { term_reason, ..response.clone() });
+
+        assert_eq!(with_term_reason(TermReason::Exited(3)), 3);
+        assert_eq!(with_term_reason(TermReason::Signaled(9)), 137);
+        assert_eq!(with_term_reason(TermReason::TimedOut), 1);
+        assert_eq!(with_term_reason(TermReason::OutOfMemory), 1);
+    }
+
+    #[test]
+    fn summary_lists_the_test_results_and_how_the_code_ended() {
+        let stdin_data = vec!["world"];
+        let expected = vec!["hello"];
+        let tests = [
+            SandboxTest { stdin_data: Some(&stdin_data), ..SandboxTest::new("stdin") },
+            SandboxTest { expected_stdout_data: Some(&expected), ..SandboxTest::new("hello") },
+        ];
+
+        let path = SandboxRequest::temp_path("cli").unwrap();
+        let request = SandboxRequest::builder()
+            .id("cli")
+            .timeout(Duration::from_secs(5))
+            .path(&path)
+            .source("print('hello')")
+            .compiler(sandbox::compiler_for("python").unwrap())
+            .tests(&tests)
+            .build()
+            .unwrap();
+
+        let backend = MockBackend::new().with_stdout("hello\n").with_exit_code(3);
+        let response = sandbox::Sandbox::new(&request).with_backend(backend).run().unwrap();
+
+        let mut output = Vec::new();
+        write_summary(&response, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "test stdin: not run\ntest hello: passed\nexited with code 3\n");
+
+        let signaled = SandboxResponse { term_reason: TermReason::Signaled(9), test_results: Vec::new(), ..response };
+        let mut output = Vec::new();
+        write_summary(&signaled, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "terminated by signal 9\n");
+    }
+
+    #[test]
+    fn list_languages_includes_the_built_in_compilers() {
+        let mut output = Vec::new();
+        list_languages(&CompilerRegistry::new(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.lines().any(|line| line == "python\tpython_virtual_machine"));
+        assert!(output.lines().any(|line| line == "Javascript\tnode_virtual_machine"));
+    }
+
+    #[test]
+    fn list_languages_includes_compilers_registered_at_runtime() {
+        register_compiler(&DART);
+
+        let mut output = Vec::new();
+        list_languages(&default_registry(), &mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().lines().any(|line| line == "dart\tdart_virtual_machine"));
+    }
+
+    #[test]
+    fn list_languages_writes_a_line_per_language_in_order() {
+        let registry = CompilerRegistry::new();
+
+        let mut output = Vec::new();
+        list_languages(&registry, &mut output).unwrap();
+
+        let languages: Vec<String> = String::from_utf8(output).unwrap().lines()
+            .map(|line| line.split('\t').next().unwrap().to_string())
+            .collect();
+
+        assert_eq!(languages, registry.languages());
+    }
 }
@@ -0,0 +1,310 @@
+use crate::backend::{DockerBackend, ExecutionBackend};
+use crate::sandbox::{Sandbox, SandboxError, SandboxRequest, SandboxResponse};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+
+/// A pool that bounds the number of sandboxes that are executing at once, this is used to ensure
+/// the host is not overwhelmed by the number of containers running. Requests that are executed
+/// once the pool is full wait for a running sandbox to complete rather than failing.
+pub struct SandboxPool {
+    /// The max number of sandboxes that can be executing at once.
+    max_concurrency: usize,
+    /// The number of sandboxes that are currently executing.
+    running: Mutex<usize>,
+    /// Notified each time a executing sandbox releases its permit.
+    released: Condvar,
+}
+
+/// A permit to execute a sandbox within the pool, the permit is released back to the pool once it
+/// has been dropped.
+pub struct SandboxPermit<'a> {
+    pool: &'a SandboxPool,
+}
+
+impl SandboxPool {
+    /// Creates a new pool which allows the given number of sandboxes to execute at once, the pool
+    /// always allows at least a single sandbox to execute.
+    ///
+    /// # Arguments
+    /// * `max_concurrency` - The max number of sandboxes that can be executing at once.
+    pub fn new(max_concurrency: usize) -> SandboxPool {
+        SandboxPool {
+            max_concurrency: max_concurrency.max(1),
+            running: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// The max number of sandboxes that can be executing at once.
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    /// The number of sandboxes that are currently executing within the pool.
+    pub fn running(&self) -> usize {
+        *self.running.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Acquires a permit to execute within the pool, blocking until a permit is free if the pool
+    /// is already running the max number of sandboxes.
+    pub fn acquire(&self) -> SandboxPermit<'_> {
+        let running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut running = self.released
+            .wait_while(running, |running| *running >= self.max_concurrency)
+            .unwrap_or_else(PoisonError::into_inner);
+
+        *running += 1;
+        SandboxPermit { pool: self }
+    }
+
+    /// Executes the given request within the pool, waiting until a permit is free before the
+    /// sandbox is executed. The permit is released once the execution has completed.
+    ///
+    /// # Arguments
+    /// * `request` - The sandbox request that will be executed.
+    pub fn run(&self, request: &SandboxRequest) -> Result<SandboxResponse, SandboxError> {
+        self.run_with_backend(request, &DockerBackend::default())
+    }
+
+    /// Executes the given request within the pool with the given backend, waiting until a permit
+    /// is free before the sandbox is executed. The permit is released once the execution has
+    /// completed.
+    ///
+    /// # Arguments
+    /// * `request` - The sandbox request that will be executed.
+    /// * `backend` - The backend the request is executed with.
+    pub fn run_with_backend(&self, request: &SandboxRequest, backend: &dyn ExecutionBackend) -> Result<SandboxResponse, SandboxError> {
+        let _permit = self.acquire();
+        Sandbox::new(request).with_backend(backend).run()
+    }
+}
+
+impl Drop for SandboxPermit<'_> {
+    fn drop(&mut self) {
+        let mut running = self.pool.running.lock().unwrap_or_else(PoisonError::into_inner);
+        *running -= 1;
+
+        self.pool.released.notify_one();
+    }
+}
+
+/// Runs all of the given requests, executing at most the given number of requests at once across a
+/// pool of threads. The results are returned in the same order as the requests. Each request is
+/// executed within its own container, a request which shares its path with a earlier request is
+/// not executed and results in a invalid request error so that requests never share a directory.
+///
+/// # Arguments
+/// * `requests` - The sandbox requests that will be executed.
+/// * `concurrency` - The max number of requests that can be executing at once.
+pub fn run_all(requests: &[SandboxRequest], concurrency: usize) -> Vec<Result<SandboxResponse, SandboxError>> {
+    run_all_with_backend(requests, concurrency, &DockerBackend::default())
+}
+
+/// Runs all of the given requests with the given backend, the same as `run_all` but with every
+/// request executed by the given backend rather than the docker backend.
+///
+/// # Arguments
+/// * `requests` - The sandbox requests that will be executed.
+/// * `concurrency` - The max number of requests that can be executing at once.
+/// * `backend` - The backend every request is executed with.
+pub fn run_all_with_backend(requests: &[SandboxRequest],
+                            concurrency: usize,
+                            backend: &dyn ExecutionBackend) -> Vec<Result<SandboxResponse, SandboxError>> {
+    let results: Vec<Mutex<Option<Result<SandboxResponse, SandboxError>>>> = requests.iter().map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency.max(1).min(requests.len()) {
+            scope.spawn(|| {
+                // Each of the workers takes the next request that has not yet been taken until all
+                // of the requests have been executed.
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+
+                    let request = match requests.get(index) {
+                        Some(request) => request,
+                        None => break,
+                    };
+
+                    let result = if requests[..index].iter().any(|earlier| earlier.path == request.path) {
+                        Err(SandboxError::InvalidRequest(
+                            format!("path {} is shared with another request", request.path.display())))
+                    } else {
+                        Sandbox::new(request).with_backend(backend).run()
+                    };
+
+                    *results[index].lock().unwrap_or_else(PoisonError::into_inner) = Some(result);
+                }
+            });
+        }
+    });
+
+    results.into_iter()
+        .map(|result| result.into_inner().unwrap_or_else(PoisonError::into_inner).expect("every request is executed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::{compiler_for, ContainerExit, PreparedPaths};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::AtomicUsize;
+    use std::time::{Duration, SystemTime};
+
+    /// A backend which writes the source code of each request as its output after a short delay,
+    /// recording the most executions that were running at once.
+    #[derive(Default)]
+    struct EchoBackend {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    impl ExecutionBackend for EchoBackend {
+        fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, _timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+
+            thread::sleep(Duration::from_millis(20));
+            std::fs::write(&prepared.stdout_file, request.source_code)?;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            let now = SystemTime::now();
+
+            let container_exit = ContainerExit {
+                status_code: Some(0),
+                duration: Duration::default(),
+                started_at: now,
+                finished_at: now,
+                timed_out: false,
+                cancelled: false,
+                logs: None,
+                oom_killed: None,
+                compile_cache_hit: false,
+            };
+
+            Ok(SandboxResponse::from_request(request, prepared, container_exit)?)
+        }
+    }
+
+    /// Builds a request for the python compiler with the given path and source code.
+    fn request<'a>(path: &'a Path, source: &'a str) -> SandboxRequest<'a> {
+        SandboxRequest::builder()
+            .id("pool")
+            .timeout(Duration::from_secs(5))
+            .path(path)
+            .source(source)
+            .compiler(compiler_for("python").unwrap())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn run_all_returns_the_results_in_the_order_of_the_requests() {
+        let sources: Vec<String> = (0..8).map(|index| format!("request {}", index)).collect();
+        let paths: Vec<PathBuf> = sources.iter().map(|_| SandboxRequest::temp_path("pool").unwrap()).collect();
+        let requests: Vec<SandboxRequest> = paths.iter().zip(&sources).map(|(path, source)| request(path, source)).collect();
+
+        let results = run_all_with_backend(&requests, 3, &EchoBackend::default());
+        let outputs: Vec<String> = results.into_iter().map(|result| result.unwrap().stdout).collect();
+
+        assert_eq!(outputs, sources);
+    }
+
+    #[test]
+    fn run_all_never_runs_more_than_the_concurrency() {
+        let paths: Vec<PathBuf> = (0..8).map(|_| SandboxRequest::temp_path("pool").unwrap()).collect();
+        let requests: Vec<SandboxRequest> = paths.iter().map(|path| request(path, "print(1)")).collect();
+        let backend = EchoBackend::default();
+
+        assert!(run_all_with_backend(&requests, 2, &backend).iter().all(Result::is_ok));
+        assert_eq!(backend.max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn run_all_rejects_requests_sharing_a_path() {
+        let path = SandboxRequest::temp_path("pool").unwrap();
+        let requests = [request(&path, "first"), request(&path, "second")];
+
+        let results = run_all_with_backend(&requests, 2, &EchoBackend::default());
+
+        assert_eq!(results[0].as_ref().unwrap().stdout, "first");
+        assert!(matches!(results[1], Err(SandboxError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn pool_never_runs_more_than_the_max_concurrency() {
+        let pool = SandboxPool::new(2);
+        let backend = EchoBackend::default();
+        let paths: Vec<PathBuf> = (0..6).map(|_| SandboxRequest::temp_path("pool").unwrap()).collect();
+
+        thread::scope(|scope| {
+            for path in &paths {
+                let (pool, backend) = (&pool, &backend);
+                scope.spawn(move || pool.run_with_backend(&request(path, "print(1)"), backend).unwrap());
+            }
+        });
+
+        assert_eq!(backend.max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.running(), 0);
+    }
+
+    #[test]
+    fn pool_allows_at_least_a_single_sandbox() {
+        assert_eq!(SandboxPool::new(0).max_concurrency(), 1);
+    }
+
+    #[test]
+    fn permit_is_released_when_the_run_fails() {
+        let pool = SandboxPool::new(1);
+
+        let result = pool.run_with_backend(&request(Path::new("src"), "print(1)"), &EchoBackend::default());
+        assert!(matches!(result, Err(SandboxError::InvalidRequest(_))));
+        assert_eq!(pool.running(), 0);
+    }
+
+    #[test]
+    fn acquire_waits_for_a_permit_to_be_released() {
+        let pool = SandboxPool::new(1);
+        let permit = pool.acquire();
+
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| drop(pool.acquire()));
+
+            thread::sleep(Duration::from_millis(50));
+            assert!(!waiting.is_finished());
+            assert_eq!(pool.running(), 1);
+
+            drop(permit);
+            waiting.join().unwrap();
+        });
+
+        assert_eq!(pool.running(), 0);
+    }
+
+    #[test]
+    fn run_all_executes_every_request_within_its_own_directory() {
+        let paths: Vec<PathBuf> = (0..4).map(|_| SandboxRequest::temp_path("pool").unwrap()).collect();
+        let sources: Vec<String> = (0..4).map(|index| format!("request {}", index)).collect();
+        let requests: Vec<SandboxRequest> = paths.iter().zip(&sources)
+            .map(|(path, source)| SandboxRequest { keep_artifacts: true, ..request(path, source) })
+            .collect();
+
+        let results = run_all_with_backend(&requests, 4, &EchoBackend::default());
+
+        for ((result, path), source) in results.iter().zip(&paths).zip(&sources) {
+            let artifacts_path = result.as_ref().unwrap().artifacts_path.as_deref().unwrap();
+
+            assert_eq!(artifacts_path, path);
+            assert_eq!(std::fs::read_to_string(artifacts_path.join("python.out")).unwrap(), *source);
+            std::fs::remove_dir_all(artifacts_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn run_all_of_no_requests_returns_no_results() {
+        assert!(run_all_with_backend(&[], 4, &EchoBackend::default()).is_empty());
+    }
+}
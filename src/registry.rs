@@ -0,0 +1,163 @@
+use crate::sandbox::{LanguageCompiler, COMPILERS};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock, RwLockReadGuard};
+
+/// A registry of the compilers that can be used to execute requests, keyed by the language of the
+/// compiler. The registry is seeded with the built in compilers and further compilers can be
+/// registered at runtime, allowing languages that are not shipped with the crate to be supported.
+#[derive(Debug, Clone)]
+pub struct CompilerRegistry<'a> {
+    /// The registered compilers keyed by the lowercase language of the compiler, so that languages
+    /// are matched regardless of case.
+    compilers: HashMap<String, &'a LanguageCompiler<'a>>,
+}
+
+impl<'a> CompilerRegistry<'a> {
+    /// Creates a new registry seeded with all of the built in compilers.
+    pub fn new() -> CompilerRegistry<'a> {
+        let mut registry = CompilerRegistry { compilers: HashMap::with_capacity(COMPILERS.len()) };
+
+        for compiler in COMPILERS {
+            registry.register(compiler);
+        }
+
+        registry
+    }
+
+    /// Registers the given compiler for its language, replacing and returning any compiler that
+    /// was already registered for the same language.
+    ///
+    /// # Arguments
+    /// * `compiler` - The compiler being registered.
+    pub fn register(&mut self, compiler: &'a LanguageCompiler<'a>) -> Option<&'a LanguageCompiler<'a>> {
+        self.compilers.insert(compiler.language.to_ascii_lowercase(), compiler)
+    }
+
+    /// Gets the compiler registered for the given language, the language is matched regardless of
+    /// case (e.g "python", "Python" and "PYTHON" are all the same language).
+    ///
+    /// # Arguments
+    /// * `language` - The name of the language a compiler is being looked up for.
+    pub fn get(&self, language: &str) -> Option<&'a LanguageCompiler<'a>> {
+        self.compilers.get(&language.to_ascii_lowercase()).copied()
+    }
+
+    /// The languages of all the registered compilers, sorted by the name of the language.
+    pub fn languages(&self) -> Vec<&'a str> {
+        let mut languages: Vec<&'a str> = self.compilers.values().map(|compiler| compiler.language).collect();
+        languages.sort_unstable_by_key(|language| language.to_ascii_lowercase());
+
+        languages
+    }
+}
+
+impl Default for CompilerRegistry<'_> {
+    fn default() -> Self {
+        CompilerRegistry::new()
+    }
+}
+
+/// The registry shared for the lifetime of the process, seeded with the built in compilers the
+/// first time that it is used.
+fn default_registry_lock() -> &'static RwLock<CompilerRegistry<'static>> {
+    static DEFAULT_REGISTRY: OnceLock<RwLock<CompilerRegistry<'static>>> = OnceLock::new();
+    DEFAULT_REGISTRY.get_or_init(|| RwLock::new(CompilerRegistry::new()))
+}
+
+/// The default registry of the process, the built in compilers along with every compiler that has
+/// been registered with `register_compiler`. This is the registry `compiler_for` looks up compilers
+/// within. The registry is locked for reading until the returned guard is dropped.
+pub fn default_registry() -> RwLockReadGuard<'static, CompilerRegistry<'static>> {
+    // Registering a compiler cannot leave the registry in an invalid state, so a poisoned lock is
+    // still used.
+    default_registry_lock().read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Registers the given compiler within the default registry of the process for its language, so
+/// that the compiler is found by `compiler_for`. Replacing and returning any compiler that was
+/// already registered for the same language.
+///
+/// # Arguments
+/// * `compiler` - The compiler being registered.
+///
+/// # Example
+/// ```
+/// # use compileme::registry::{default_registry, register_compiler};
+/// # use compileme::sandbox::{self, LanguageCompiler};
+/// static DART: LanguageCompiler = LanguageCompiler {
+///     language: "dart",
+///     compiler: "dart",
+///     source_extension: "dart",
+///     source_file: None,
+///     interpreter: true,
+///     additional_arguments: None,
+///     compiled_output: None,
+///     virtual_machine_name: "dart_virtual_machine",
+///     runtime_image: None,
+///     env: &[],
+///     standard_output_file: "dart.out",
+///     standard_error_file: "dart.error.out",
+/// };
+///
+/// register_compiler(&DART);
+///
+/// assert_eq!(sandbox::compiler_for("Dart"), Some(&DART));
+/// assert!(default_registry().languages().contains(&"dart"));
+/// ```
+pub fn register_compiler(compiler: &'static LanguageCompiler<'static>) -> Option<&'static LanguageCompiler<'static>> {
+    default_registry_lock().write().unwrap_or_else(|poisoned| poisoned.into_inner()).register(compiler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sandbox::compiler_for;
+    use crate::testing::DART;
+
+
+    #[test]
+    fn new_registry_is_seeded_with_the_built_in_compilers() {
+        let registry = CompilerRegistry::new();
+
+        assert_eq!(registry.languages().len(), COMPILERS.len());
+        assert_eq!(registry.get("PYTHON").map(|compiler| compiler.compiler), Some("python3"));
+        assert!(registry.get("dart").is_none());
+    }
+
+    #[test]
+    fn registered_compiler_is_resolved_regardless_of_case() {
+        let mut registry = CompilerRegistry::new();
+
+        assert!(registry.register(&DART).is_none());
+        assert_eq!(registry.get("Dart"), Some(&DART));
+        assert!(registry.languages().contains(&"dart"));
+    }
+
+    #[test]
+    fn registering_a_language_again_replaces_the_compiler() {
+        let mut registry = CompilerRegistry::new();
+        let python = LanguageCompiler { compiler: "python3.12", ..*registry.get("python").unwrap() };
+
+        let replaced = registry.register(&python);
+
+        assert_eq!(replaced.map(|compiler| compiler.compiler), Some("python3"));
+        assert_eq!(registry.get("python").map(|compiler| compiler.compiler), Some("python3.12"));
+        assert_eq!(registry.languages().len(), COMPILERS.len());
+    }
+
+    #[test]
+    fn compiler_registered_at_runtime_is_found_by_compiler_for() {
+        register_compiler(&DART);
+
+        assert_eq!(compiler_for("dart"), Some(&DART));
+        assert!(default_registry().languages().contains(&"dart"));
+    }
+
+    #[test]
+    fn languages_are_sorted_regardless_of_case() {
+        let mut registry = CompilerRegistry::new();
+        registry.register(&DART);
+
+        assert_eq!(registry.languages(), ["cpp", "dart", "go", "java", "Javascript", "python", "rust"]);
+    }
+}
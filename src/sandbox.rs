@@ -1,28 +1,62 @@
-use std::{io, env};
-use std::env::join_paths;
-use std::path::Path;
-use std::fs::File;
-use std::io::{Write, BufWriter};
+use crate::backend::{ContainerRuntime, DockerBackend, ExecutionBackend};
+use crate::compare::{ComparisonMode, OutputComparator, line_diff};
+use crate::registry::default_registry;
+use crate::usage::ResourceUsage;
+use std::{io, env, fmt};
+use std::path::{Component, Path, PathBuf};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub struct LanguageCompiler<'a> {
     /// The language that the given compiler is going to be using or not. This is the can be seen
     /// as the kind of code that is going to be executed by the requesting machine. e.g Python, Node,
     /// JavaScript, C++.
-    language: &'a str,
+    pub language: &'a str,
     /// The name of the compiler that will be used to run the code. This is the name of the file that
     /// will be called from the root of the docker container. e.g node, py, python3
-    compiler: &'a str,
+    pub compiler: &'a str,
+    /// The file extension of the source file that will be written for the compiler, most compilers
+    /// require the correct extension to be able to parse the given file. e.g py, js, cpp
+    pub source_extension: &'a str,
+    /// The name of the source file that will be written for the compiler, overriding the name made
+    /// from the language and the source extension. This is required by compilers which expect the
+    /// file to be named after its contents, e.g Main.java for a public class Main.
+    pub source_file: Option<&'a str>,
     /// If the given compiler is a interpreter or not, since based on this action we would need to
     /// create /// additional steps for compiling to a file if not.
     pub interpreter: bool,
     /// The additional arguments that might be required for performing compiling actions.
-    /// For example letting a compiler to know that they need to build first.
+    /// For example letting a compiler to know that they need to build first. These are passed to
+    /// the compiler before the source file for both compilers and interpreters, e.g -O2, build.
     pub additional_arguments: Option<&'a str>,
+    /// The command that will be used to execute the output of the compiler, this is only used when
+    /// the compiler is not a interpreter and will only be executed if the compiling succeeded. The
+    /// output is written outside of the read only request path mount.
+    /// e.g /tmp/cpp.o, java -cp /tmp Main
+    pub compiled_output: Option<&'a str>,
     /// This is the name of docker image that will be executed for the given code sample, this will
-    /// be the container that will be used for just this language. Most likely virtual_machine_language,
-    /// e.g virtual_machine_python.
+    /// be the container that will be used for just this language. Most likely language_virtual_machine,
+    /// e.g python_virtual_machine.
     pub virtual_machine_name: &'a str,
+    /// The name of the docker image that the compiled output is executed within, this is only used
+    /// when the compiler is not a interpreter. When provided the source code is compiled within the
+    /// image of the compiler and the compiled output is passed through a shared volume (mounted to
+    /// /tmp) to a separate container of this image, so that the untrusted code is not executed
+    /// alongside the build toolchain. The image must provide a shell. When not provided both the
+    /// compiling and the executing happen within the image of the compiler.
+    pub runtime_image: Option<&'a str>,
+    /// The environment variables that the compiler requires to be set, as pairs of the variable
+    /// name and value. These are set after the default environment variables and before the
+    /// environment variables of the request, e.g a writable HOME for a user without a home.
+    pub env: &'a [(&'a str, &'a str)],
     ///  The file in which the given compiler will be writing too (standard output), since this file
     /// will be read when the response returned back to the user.
     pub standard_output_file: &'a str,
@@ -32,28 +66,221 @@ pub struct LanguageCompiler<'a> {
 }
 
 
+impl LanguageCompiler<'_> {
+    /// The name of the source file that the source code will be written to for the compiler, the
+    /// source file of the compiler when given otherwise using the source extension of the compiler.
+    pub fn source_file_name(&self) -> String {
+        match self.source_file {
+            Some(source_file) => source_file.to_string(),
+            None => format!("{}.{}", self.language, self.source_extension),
+        }
+    }
+}
+
 // a list of compilers and the details for the given compilers. Including the details of the compiler
 // language, the name of the compiler entry point and the file that the output will be written too.
 // once the container has executed and been removed, the file should contain the output content. If the
 // container reaches its limits, then
-pub const COMPILERS: [&'static LanguageCompiler; 2] = [&LanguageCompiler {
+pub const COMPILERS: [&LanguageCompiler; 6] = [&LanguageCompiler {
     language: "python",
     compiler: "python3",
+    source_extension: "py",
+    source_file: None,
     interpreter: true,
     additional_arguments: None,
+    compiled_output: None,
     virtual_machine_name: "python_virtual_machine",
+    runtime_image: None,
+    env: &[],
     standard_output_file: "python.out",
     standard_error_file: "python.error.out",
 }, &LanguageCompiler {
     language: "Javascript",
-    compiler: &"node",
+    compiler: "node",
+    source_extension: "js",
+    source_file: None,
     interpreter: true,
     additional_arguments: None,
+    compiled_output: None,
     virtual_machine_name: "node_virtual_machine",
+    runtime_image: None,
+    env: &[],
     standard_output_file: "node.out",
     standard_error_file: "node.error.out",
+}, &LanguageCompiler {
+    language: "cpp",
+    compiler: "g++",
+    source_extension: "cpp",
+    source_file: None,
+    interpreter: false,
+    additional_arguments: Some("-o /tmp/cpp.o"),
+    compiled_output: Some("/tmp/cpp.o"),
+    virtual_machine_name: "cpp_virtual_machine",
+    runtime_image: None,
+    env: &[],
+    standard_output_file: "cpp.out",
+    standard_error_file: "cpp.error.out",
+}, &LanguageCompiler {
+    language: "java",
+    compiler: "javac",
+    source_extension: "java",
+    source_file: Some("Main.java"),
+    interpreter: false,
+    additional_arguments: Some("-d /tmp"),
+    compiled_output: Some("java -cp /tmp Main"),
+    virtual_machine_name: "java_virtual_machine",
+    runtime_image: None,
+    env: &[],
+    standard_output_file: "java.out",
+    standard_error_file: "java.error.out",
+}, &LanguageCompiler {
+    language: "go",
+    compiler: "go",
+    source_extension: "go",
+    source_file: None,
+    interpreter: false,
+    additional_arguments: Some("build -o /tmp/go.o"),
+    compiled_output: Some("/tmp/go.o"),
+    virtual_machine_name: "go_virtual_machine",
+    runtime_image: None,
+    env: &[("HOME", "/tmp"), ("GOCACHE", "/tmp/go-cache")],
+    standard_output_file: "go.out",
+    standard_error_file: "go.error.out",
+}, &LanguageCompiler {
+    language: "rust",
+    compiler: "rustc",
+    source_extension: "rs",
+    source_file: None,
+    interpreter: false,
+    additional_arguments: Some("-o /tmp/rust.o"),
+    compiled_output: Some("/tmp/rust.o"),
+    virtual_machine_name: "rust_virtual_machine",
+    runtime_image: None,
+    env: &[],
+    standard_output_file: "rust.out",
+    standard_error_file: "rust.error.out",
 }];
 
+/// Finds the compiler for the given language from the default registry of supported compilers, the
+/// language is matched regardless of case (e.g "python", "Python" and "PYTHON" are all the same
+/// language). This includes compilers registered at runtime with `register_compiler`, compilers
+/// registered on other registries are found with the registry itself.
+///
+/// # Arguments
+/// * `language` - The name of the language a compiler is being looked up for.
+pub fn compiler_for(language: &str) -> Option<&'static LanguageCompiler<'static>> {
+    default_registry().get(language)
+}
+
+/// The contents of the standard input file for the given lines of standard input data, each line
+/// is written on its own line and ends with a new line (including the last line). Programs reading
+/// the input line by line expect every line to be terminated, a missing trailing new line would
+/// otherwise leave the last line unread by some readers.
+///
+/// # Arguments
+/// * `stdin_data` - The lines of the standard input data.
+///
+/// # Example
+/// ```
+/// # use compileme::sandbox::stdin_contents;
+/// let stdin = stdin_contents(&["1", "2", "3"]);
+///
+/// assert_eq!(stdin, "1\n2\n3\n");
+/// assert_eq!(stdin.lines().count(), 3);
+/// assert_eq!(stdin_contents(&[]), "");
+/// ```
+pub fn stdin_contents(stdin_data: &[&str]) -> String {
+    stdin_data.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// Generates a random suffix which is unique within the process and between processes, used to
+/// ensure the names of paths and containers never collide. The random state is seeded randomly,
+/// hashing the process, the current time and a count of the generated suffixes ensures the suffix
+/// is unique even when the same seed is produced.
+pub(crate) fn unique_suffix() -> u64 {
+    static SUFFIX_COUNT: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.write_u128(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos());
+    hasher.write_u64(SUFFIX_COUNT.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+/// Sanitizes the given request id so that it is safe to use within file and container names, any
+/// character that is not a letter, digit, underscore, dot or dash is replaced with an underscore
+/// (e.g "../x y" becomes ".._x_y"). Since the sanitized id is only ever used after a prefix it can
+/// never form a path component by itself. Ids that do not contain any letters or digits cannot be
+/// sanitized to anything meaningful and are rejected as an invalid request.
+///
+/// # Arguments
+/// * `id` - The id of the request being sanitized.
+pub(crate) fn sanitize_id(id: &str) -> Result<String, SandboxError> {
+    let sanitized: String = id.chars()
+        .map(|character| match character {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | '-' => character,
+            _ => '_',
+        })
+        .collect();
+
+    if !sanitized.chars().any(|character| character.is_ascii_alphanumeric()) {
+        return Err(SandboxError::InvalidRequest(format!("id {:?} does not contain any letters or digits", id)));
+    }
+
+    Ok(sanitized)
+}
+
+/// The default path of the script that is executed within the container, relative to the current
+/// directory.
+const DEFAULT_SCRIPT_PATH: &str = "dockerFiles/script.sh";
+
+/// The name of the file within the request path that will contain the standard input data which
+/// is directed into the executed code.
+pub(crate) const STANDARD_INPUT_FILE: &str = "stdin";
+
+/// The max number of bytes of the source code of a request when the request does not provide a
+/// max, larger source code is rejected before it is written into the request path.
+const DEFAULT_MAX_SOURCE_BYTES: usize = 1024 * 1024;
+
+/// The name of the file within the request path that the resource usage of the executed code is
+/// written to by the time binary, when the request is measuring resources.
+pub(crate) const RESOURCE_USAGE_FILE: &str = "usage";
+
+/// The directory under the current directory that request paths are allowed within when the
+/// sandbox has no allowed root, alongside the system temp directory.
+const DEFAULT_ALLOWED_ROOT: &str = "temp";
+
+/// The name of the script file that is copied into the request path and executed within the
+/// container.
+pub(crate) const SCRIPT_FILE: &str = "script.sh";
+
+/// The marker that is written to the standard output by the script once the executed code has
+/// completed, followed by the runtime of the code, the peak memory usage of the container in bytes,
+/// the cpu time of the container in nanoseconds and the number of times the process limit of the
+/// container was reached (each a dash when not available).
+pub(crate) const END_OF_OUTPUT_MARKER: &str = "*-COMPILE::EOF-*";
+
+/// The max number of bytes of the line of the end of output marker and the usage that follows it,
+/// which is read beyond the max output bytes of a request so the marker is never truncated.
+const END_OF_OUTPUT_MAX_BYTES: usize = 256;
+
+/// The name of the file within the request path that the script writes the stage the execution
+/// ended at to (compile or run), once the compiler or the compiled output has exited. The stage is
+/// followed by the number of the signal that terminated the executed code, a dash when the code
+/// exited by itself, or a question mark when the script could not tell the two apart. The status is
+/// kept out of the output of the executed code, so no output can be mistaken for the status.
+pub(crate) const STATUS_FILE: &str = "status";
+
+/// The stage written to the status file when the compiling of the source code failed, in which
+/// case the compiled output was never executed.
+pub(crate) const COMPILE_FAILED_STATUS: &str = "compile";
+
+/// The stage written to the status file when the compiled output (or the interpreter) was executed.
+pub(crate) const RUN_STATUS: &str = "run";
+
+/// The signal written to the status file when the executed code exited by itself.
+pub(crate) const NO_SIGNAL_STATUS: &str = "-";
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum SandboxTestResult {
     /// The test case has not yet executed. This is the default case for the test. And should only
@@ -65,14 +292,24 @@ pub enum SandboxTestResult {
     Passed,
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+impl fmt::Display for SandboxTestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxTestResult::NotRan => write!(f, "not run"),
+            SandboxTestResult::Failed => write!(f, "failed"),
+            SandboxTestResult::Passed => write!(f, "passed"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub struct SandboxTest<'a> {
     /// The internal id of the test, this will be used to ensure that when the response comes
     /// through that there is a related id to match it up with th request.
     pub id: &'a str,
     /// The standard input data that will be used with the given code file. This can be used for when
     /// projects require that a given code input should  be executing after reading input. e.g taking
-    /// in a input and performing actions on it.
+    /// in a input and performing actions on it. Each entry is written as its own line.
     pub stdin_data: Option<&'a Vec<&'a str>>,
     /// The expected standard output for the test case. After execution of the standard input, and
     /// the data has been returned. This is what we are going to ensure the given test case matches
@@ -81,96 +318,2984 @@ pub struct SandboxTest<'a> {
     /// The output result of the test case for the given test. With support for marking the test
     /// as not yet ran.
     pub result: SandboxTestResult,
+    /// The mode used to compare the actual standard output against the expected standard output,
+    /// allowing differences in whitespace to be ignored when the formatting is not important.
+    pub comparison: ComparisonMode,
+    /// The custom comparator used to compare the actual standard output against the expected
+    /// standard output instead of the comparison mode, when provided. This allows any accepted
+    /// output to pass when there are many correct answers. The exact bytes are still compared
+    /// when the test has expected bytes.
+    pub comparator: Option<&'a dyn OutputComparator>,
+    /// If the letter case of the output should be ignored when comparing the actual standard output
+    /// against the expected standard output, e.g when both "YES" and "yes" are accepted.
+    pub case_insensitive: bool,
+    /// The max amount of time the code is allowed to run for with this test, when not provided the
+    /// timeout of the request is used. Exceeding the timeout only fails this test.
+    pub timeout: Option<Duration>,
+    /// The exit code the executed code is expected to exit with, when provided the test only passes
+    /// if both the standard output and the exit code match. When not provided the exit code is not
+    /// checked.
+    pub expected_exit_code: Option<i32>,
+    /// The exact bytes the executed code is expected to write to the standard output, when provided
+    /// these are compared against the raw bytes of the output rather than comparing the lines of
+    /// the expected standard output. This is used when the output is binary.
+    pub expected_stdout_bytes: Option<&'a [u8]>,
+}
+
+impl<'a> SandboxTest<'a> {
+    /// Creates a new test with the given id that has not yet been ran, without any standard input,
+    /// expected output or expected exit code. The remaining fields are set with the struct update
+    /// syntax.
+    ///
+    /// # Arguments
+    /// * `id` - The id of the test.
+    ///
+    /// # Example
+    /// ```
+    /// # use compileme::sandbox::{SandboxTest, SandboxTestResult};
+    /// let expected = vec!["hello"];
+    /// let test = SandboxTest { expected_stdout_data: Some(&expected), ..SandboxTest::new("hello") };
+    ///
+    /// assert_eq!(test.result, SandboxTestResult::NotRan);
+    /// assert_eq!(test.expected_exit_code, None);
+    /// ```
+    pub fn new(id: &'a str) -> SandboxTest<'a> {
+        SandboxTest {
+            id,
+            stdin_data: None,
+            expected_stdout_data: None,
+            result: SandboxTestResult::NotRan,
+            comparison: ComparisonMode::Exact,
+            comparator: None,
+            case_insensitive: false,
+            timeout: None,
+            expected_exit_code: None,
+            expected_stdout_bytes: None,
+        }
+    }
+
+    /// Compares the standard output of the given response against the expected standard output of
+    /// the test line by line, using the comparison mode of the test (or the custom comparator of
+    /// the test, or byte by byte against the expected bytes), and the exit code against the
+    /// expected exit code. If the test has neither an expected output or an expected exit code then
+    /// the test is treated as not being ran.
+    ///
+    /// # Arguments
+    /// * `response` - The response of the executed code.
+    pub fn compare(&self, response: &SandboxResponse) -> SandboxTestResult {
+        let output_matches = match (self.expected_stdout_bytes, self.expected_stdout_data, self.comparator) {
+            (Some(expected_bytes), _, _) => Some(response.stdout_bytes == expected_bytes),
+            (None, Some(expected), Some(comparator)) => {
+                // Both outputs are given to the comparator in the same form, the lines joined by new
+                // lines without a final new line, so line endings (e.g CRLF) never differ.
+                let actual: Vec<&str> = response.stdout.lines().collect();
+                Some(comparator.compare(&expected.join("\n"), &actual.join("\n")))
+            }
+            (None, Some(expected), None) => Some(self.comparison.matches(expected, &response.stdout, self.case_insensitive)),
+            (None, None, _) => None,
+        };
+
+        let exit_code_matches = self.expected_exit_code
+            .map(|expected_exit_code| response.exit_code == Some(expected_exit_code));
+
+        match (output_matches, exit_code_matches) {
+            (None, None) => SandboxTestResult::NotRan,
+            (Some(false), _) | (_, Some(false)) => SandboxTestResult::Failed,
+            _ => SandboxTestResult::Passed,
+        }
+    }
+
+    /// Evaluates the given response of the executed code against the test, producing the
+    /// response of the test. When the test has failed the response contains a diff between the
+    /// expected output and the actual output, and the index of the first line which did not match.
+    ///
+    /// # Arguments
+    /// * `response` - The response of the executed code.
+    pub fn evaluate(&self, response: &SandboxResponse) -> SandboxTestResponse {
+        let result = self.compare(response);
+        let stdout = &response.stdout;
+
+        // A test can fail only on the exit code, in which case the output matched and there is no
+        // diff or mismatching line to report. Outputs compared as bytes (or by a custom comparator)
+        // have no lines to report.
+        let first_mismatch_line = match (result, self.expected_stdout_bytes, self.expected_stdout_data, self.comparator) {
+            (SandboxTestResult::Failed, None, Some(expected), None) => self.comparison.first_mismatch(expected, stdout, self.case_insensitive),
+            _ => None,
+        };
+
+        let diff = first_mismatch_line
+            .and(self.expected_stdout_data)
+            .map(|expected| line_diff(expected, &stdout.lines().collect::<Vec<&str>>()));
+
+        SandboxTestResponse { id: self.id.to_string(), result, diff, first_mismatch_line, timed_out: false }
+    }
+
+    /// Creates the response of the test for when the test was never executed.
+    fn not_ran(&self) -> SandboxTestResponse {
+        SandboxTestResponse { id: self.id.to_string(), result: SandboxTestResult::NotRan, diff: None, first_mismatch_line: None, timed_out: false }
+    }
+
+    /// Creates the response of the test for when the test was killed for exceeding its timeout.
+    fn timed_out(&self) -> SandboxTestResponse {
+        SandboxTestResponse { id: self.id.to_string(), result: SandboxTestResult::Failed, diff: None, first_mismatch_line: None, timed_out: true }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct SandboxTestResponse {
+    /// The internal id of the test that the response is for.
+    pub id: String,
+    /// The result of the test once it has been compared against the output.
+    pub result: SandboxTestResult,
+    /// The line diff between the expected standard output and the actual standard output, this is
+    /// only provided when the test has failed.
+    pub diff: Option<String>,
+    /// The index (from zero) of the first line of the normalized output which did not match the
+    /// expected output, or at which one of the outputs ran out of lines. This is only provided when
+    /// the test has failed.
+    pub first_mismatch_line: Option<usize>,
+    /// If the test was killed for running longer than its timeout, a timed out test is failed.
+    pub timed_out: bool,
 }
 
+/// A single step of a interactive execution, once the output of the executed code contains the
+/// expected text the line of the step is sent to the standard input of the code.
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct InteractiveStep<'a> {
+    /// The text that must be written by the executed code before the line is sent, e.g a prompt.
+    pub expect: &'a str,
+    /// The line that is sent to the standard input of the executed code, followed by a new line.
+    pub send: &'a str,
+}
+
+/// A entry of the transcript of a interactive execution, the entries are in the order that the
+/// output was written and the input was sent.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum TranscriptEntry {
+    /// The output written by the executed code.
+    Output(String),
+    /// A line sent to the standard input of the executed code, without the new line.
+    Input(String),
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub struct SandboxRequest<'a> {
     /// The internal id of the request, this will be used to ensure that when the response comes
     /// through that there is a related id to match it up with th request.
     pub id: &'a str,
     /// The max amount of timeout for the given executed code, if the code docker container is running
     /// for longer than the given timeout then the code is rejected. This is used to ensure that the
-    /// source code is not running for longer than required.
-    pub timeout: u8,
+    /// source code is not running for longer than required. A zero timeout is not valid, unless the
+    /// request is ran with a config which provides the default timeout.
+    pub timeout: Duration,
     /// The given path that would be mounted and shared with the given docker container. This is where
     /// the container will be reading the source code from and writing the response too. Once this has
     /// been completed, this is the path to files that will be cleaned up.
     pub path: &'a Path,
     /// The source code that will be executed, this is the code that will be written to the path and
-    /// mounted to the docker container.
-    pub source_code: &'a Vec<&'a str>,
+    /// mounted to the docker container. The code is written as is, so whitespace sensitive
+    /// languages keep their indentation.
+    pub source_code: &'a str,
     /// The reference details of the compiler that will be running the code. Including details of the
     /// language, compiler name (or interrupter) and the name of the given output file.
     pub compiler: &'a LanguageCompiler<'a>,
-    /// The related test that will be executed with the sandbox, comparing a given input with
-    /// a given output. This is a optional part since the process could just be completing the
-    /// code and not actually testing anything.
-    pub test: Option<&'a SandboxTest<'a>>,
+    /// The related tests that will be executed with the sandbox, comparing a given input with
+    /// a given output. Each test is executed against the same source code, this can be empty since
+    /// the process could just be completing the code and not actually testing anything.
+    pub tests: &'a [SandboxTest<'a>],
+    /// The max amount of memory in bytes that the container executing the code is allowed to use,
+    /// if the code exceeds this amount then the container is killed. When not provided the
+    /// container is not limited, it is recommended to set this for untrusted code (e.g 256MB).
+    pub memory_limit: Option<u64>,
+    /// The max number of cpu cores that the container executing the code is allowed to use, this
+    /// can be a fraction of a core (e.g 0.5). When not provided the container is not limited.
+    pub cpu_limit: Option<f64>,
+    /// If the container executing the code should have access to the network. This should be left
+    /// disabled for untrusted code and only enabled when the code legitimately requires it.
+    pub network_enabled: bool,
+    /// The max number of bytes of the standard output and standard error output that will be read
+    /// into the response, any output beyond this is dropped and the response is marked as
+    /// truncated. When not provided all of the output is read.
+    pub max_output_bytes: Option<usize>,
+    /// The max number of bytes of the source code, a request with larger source code is rejected
+    /// before anything is written into the request path. When not provided the source code is
+    /// limited to 1 MiB.
+    pub max_source_bytes: Option<usize>,
+    /// The environment variables that will be set within the container executing the code, as
+    /// pairs of the variable name and value. e.g ("PYTHONHASHSEED", "0")
+    pub env: &'a [(&'a str, &'a str)],
+    /// The user id that the code will be executed as within the container. When not provided the
+    /// code is executed as the nobody user (65534), rather than the root user of the container.
+    pub run_as_uid: Option<u32>,
+    /// The path of the seccomp profile that will be applied to the container executing the code.
+    /// When not provided the default seccomp profile of the container runtime is applied.
+    pub seccomp_profile: Option<&'a Path>,
+    /// If the request path and all the files written into it should be kept once the request has
+    /// been ran, rather than being cleaned up. This is used when debugging a failure to inspect the
+    /// exact files that were written and produced by the container. The kept path is returned in
+    /// the response, or within the artifacts kept error when running the request failed.
+    pub keep_artifacts: bool,
+    /// If the execution of the tests should stop after the first failed test, the remaining tests
+    /// are not executed and are marked as not being ran. This gives quicker feedback when there
+    /// are many tests.
+    pub fail_fast: bool,
+    /// The max number of processes (and threads) that can exist at once within the container
+    /// executing the code, this stops untrusted code from exhausting the host with a fork bomb.
+    /// When not provided the container is limited to 128 processes.
+    pub pids_limit: Option<u32>,
+    /// The max number of bytes of the tmpfs mounted at /tmp within the container, where compilers
+    /// write their compiled output. When provided the writes of the executed code are kept in
+    /// memory rather than written to disk, and are discarded with the container. When not provided
+    /// /tmp is part of the filesystem of the container.
+    pub tmpfs_size: Option<u64>,
+    /// If ANSI escape sequences (e.g colors) should be removed from the standard output and the
+    /// standard error output before they are compared and stored on the response. The raw bytes of
+    /// the output are left untouched.
+    pub strip_ansi: bool,
+    /// If the standard error output should be merged into the standard output, both are written to
+    /// the standard output file as a single interleaved output. This keeps the relative order of
+    /// the outputs, the standard error output of the response is then empty.
+    pub merge_output: bool,
+    /// The token that can be used to cancel the request while executing, once cancelled the
+    /// execution is stopped as soon as possible and the request fails with a cancelled error. The
+    /// sandbox environment is still cleaned up.
+    pub cancellation: Option<&'a CancellationToken>,
+    /// If the resource usage of the executed code should be measured with `/usr/bin/time -v` and
+    /// included on the response, this requires the time binary to be installed within the image
+    /// of the compiler. The usage is only measured within a container.
+    pub measure_resources: bool,
+    /// The arguments that are given to the executed code, after any arguments of the compiler. Each
+    /// argument is passed as is without being interpreted by a shell, so arguments containing
+    /// spaces or special characters are given to the code unchanged. e.g ["hello world"]
+    pub program_args: &'a [&'a str],
 }
 
-pub struct Sandbox<'a> {
-    request: &'a SandboxRequest<'a>,
-}
+impl<'a> SandboxRequest<'a> {
+    /// Creates a new builder for a sandbox request, the builder will validate that all the
+    /// required fields have been provided when the request is being built.
+    pub fn builder() -> SandboxRequestBuilder<'a> {
+        SandboxRequestBuilder::default()
+    }
 
-impl Sandbox<'_> {
-    /// Creates a new instance of the sandbox, the entry point for the container creator, management
-    /// and completion. Taking a request object that will contain all the related information for
-    /// creating the container.
+    /// Returns a copy of the request with the timeout set to the given number of seconds, a
+    /// shorthand for when whole second timeouts are all that is required.
     ///
     /// # Arguments
-    /// * `request` - The sandbox request containing the required data to crate the container.
+    /// * `seconds` - The number of seconds the executed code is allowed to run for.
+    pub fn with_timeout_secs(self, seconds: u64) -> Self {
+        SandboxRequest { timeout: Duration::from_secs(seconds), ..self }
+    }
+
+    /// Validates the request before any files are written or any containers are executed, giving a
+    /// invalid request error describing the problem when the id is empty (or cannot be sanitized),
+    /// the timeout is zero, the source code is empty (or larger than the max source bytes) or the
+    /// compiler does not have a language, compiler and image, or the language of the compiler is not
+    /// registered within the default registry. This is called by the sandbox before the request is
+    /// prepared.
     ///
     /// # Example
+    /// ```
+    /// # use compileme::sandbox::{SandboxRequest, COMPILERS};
+    /// # use std::path::Path;
+    /// # use std::time::Duration;
+    /// let request = SandboxRequest::builder()
+    ///     .id("test")
+    ///     .timeout(Duration::from_secs(1))
+    ///     .path(Path::new("./temp/validate"))
+    ///     .source("print('hello')")
+    ///     .compiler(COMPILERS[0])
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert!(request.validate().is_ok());
+    /// assert!(SandboxRequest { source_code: "", ..request }.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), SandboxError> {
+        let invalid = |reason: &str| Err(SandboxError::InvalidRequest(reason.to_string()));
+
+        if self.id.is_empty() {
+            return invalid("id is empty");
+        }
+
+        sanitize_id(self.id)?;
+
+        if self.timeout.is_zero() {
+            return invalid("timeout is zero or was not provided");
+        }
+
+        if self.source_code.is_empty() {
+            return invalid("source code is empty");
+        }
+
+        let max_source_bytes = self.max_source_bytes.unwrap_or(DEFAULT_MAX_SOURCE_BYTES);
+
+        if self.source_code.len() > max_source_bytes {
+            return Err(SandboxError::InvalidRequest(format!("source code is larger than {} bytes", max_source_bytes)));
+        }
+
+        let compiler = self.compiler;
+
+        if compiler.language.is_empty() || compiler.compiler.is_empty() || compiler.virtual_machine_name.is_empty() {
+            return invalid("compiler is missing a language, compiler or image");
+        }
+
+        // The compiler must be registered for its language, so a request can never execute a
+        // compiler that the registry does not know about.
+        if default_registry().get(compiler.language).is_none() {
+            return Err(SandboxError::InvalidRequest(format!("unknown language {}", compiler.language)));
+        }
+
+        Ok(())
+    }
+
+    /// Allocates a unique path under the system temp directory for a request with the given id, in
+    /// the form of compileme_{id}_{suffix}. The random suffix ensures that requests with the same
+    /// id never share a path, so callers do not need to invent a collision free path themselves.
+    /// The directory is not created, it is created by the sandbox when the request is prepared and
+    /// the returned path is removed by the sandbox once the request has been ran. The id is
+    /// sanitized the same as when naming the container, returning an invalid request error if it
+    /// cannot be.
     ///
+    /// # Arguments
+    /// * `id` - The id of the request the path is being allocated for.
+    ///
+    /// # Example
     /// ```
-    /// let sandbox = sandbox::Sandbox::new(&SandboxRequest {
-    ///   id: "1234",
-    ///   timeout: 20,
-    ///   path: Path::new("./temp/random/python_test/"),
-    ///   source_code: &"print('hello')".split_whitespace().collect::<Vec<&str>>(),
-    ///   compiler: &sandbox::COMPILERS[0],
-    ///   test: None,
-    /// });
+    /// # use compileme::sandbox::SandboxRequest;
+    /// let first = SandboxRequest::temp_path("test").unwrap();
+    /// let second = SandboxRequest::temp_path("test").unwrap();
+    ///
+    /// assert_ne!(first, second);
+    /// assert!(first.starts_with(std::env::temp_dir()));
+    ///
+    /// let escaping = SandboxRequest::temp_path("../../etc").unwrap();
+    /// assert_eq!(escaping.parent(), Some(std::env::temp_dir().as_path()));
+    ///
+    /// assert!(SandboxRequest::temp_path("../..").is_err());
     /// ```
-    pub fn new<'a>(request: &'a SandboxRequest<'a>) -> Sandbox<'a> {
-        Sandbox { request }
+    pub fn temp_path(id: &str) -> Result<PathBuf, SandboxError> {
+        let id = sanitize_id(id)?;
+        Ok(env::temp_dir().join(format!("compileme_{}_{:016x}", id, unique_suffix())))
     }
+}
 
-    /// Prepare the sandbox environment for execution, creates the temp file locations, writes down
-    /// the source code file and ensures that all properties are correct and valid for execution.
-    /// If all is prepared properly, no error will be returned.
-    fn prepare<'a>(&mut self) -> Result<(), io::Error> {
-        // Create the temporary directory that will be used for storing the source code, standard
-        // input and then the location in which the compiler will write the standard output and the
-        // standard error output. After the data is written and returned, the location will be
-        // deleted.
-        std::fs::create_dir_all(self.request.path);
+#[derive(Debug, Default, Clone)]
+pub struct SandboxRequestBuilder<'a> {
+    id: Option<&'a str>,
+    timeout: Option<Duration>,
+    path: Option<&'a Path>,
+    source_code: Option<&'a str>,
+    compiler: Option<&'a LanguageCompiler<'a>>,
+    tests: &'a [SandboxTest<'a>],
+    memory_limit: Option<u64>,
+    cpu_limit: Option<f64>,
+    network_enabled: bool,
+    max_output_bytes: Option<usize>,
+    max_source_bytes: Option<usize>,
+    env: &'a [(&'a str, &'a str)],
+    run_as_uid: Option<u32>,
+    seccomp_profile: Option<&'a Path>,
+    keep_artifacts: bool,
+    fail_fast: bool,
+    pids_limit: Option<u32>,
+    tmpfs_size: Option<u64>,
+    strip_ansi: bool,
+    merge_output: bool,
+    cancellation: Option<&'a CancellationToken>,
+    measure_resources: bool,
+    program_args: &'a [&'a str],
+}
 
-        let source_file_name = format!("{}.source", self.request.compiler.language);
-        let source_file_path = self.request.path.join(source_file_name);
+impl<'a> SandboxRequestBuilder<'a> {
+    /// Sets the internal id of the request.
+    pub fn id(mut self, id: &'a str) -> Self {
+        self.id = Some(id);
+        self
+    }
 
-        // Go through the process of writing down the source file to disk, this will be used
-        // and read again when gathering the results.
-        let mut source_file = File::create(source_file_path.as_path())?;
+    /// Sets the max amount of time the executed code is allowed to run for.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the path that will be mounted and shared with the docker container.
+    pub fn path(mut self, path: &'a Path) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// Sets the source code that will be executed.
+    pub fn source(mut self, source_code: &'a str) -> Self {
+        self.source_code = Some(source_code);
+        self
+    }
+
+    /// Sets the compiler that will be running the code.
+    pub fn compiler(mut self, compiler: &'a LanguageCompiler<'a>) -> Self {
+        self.compiler = Some(compiler);
+        self
+    }
 
-        for &source_line in self.request.source_code {
-            source_file.write_all(source_line.as_bytes());
+    /// Sets the tests that will be executed against the code, by default no tests are executed.
+    pub fn tests(mut self, tests: &'a [SandboxTest<'a>]) -> Self {
+        self.tests = tests;
+        self
+    }
+
+    /// Sets the max amount of memory in bytes the container is allowed to use.
+    pub fn memory_limit(mut self, memory_limit: u64) -> Self {
+        self.memory_limit = Some(memory_limit);
+        self
+    }
+
+    /// Sets the max number of cpu cores the container is allowed to use.
+    pub fn cpu_limit(mut self, cpu_limit: f64) -> Self {
+        self.cpu_limit = Some(cpu_limit);
+        self
+    }
+
+    /// Sets if the container should have access to the network, by default it does not.
+    pub fn network_enabled(mut self, network_enabled: bool) -> Self {
+        self.network_enabled = network_enabled;
+        self
+    }
+
+    /// Sets the max number of bytes of each of the outputs that will be read into the response.
+    pub fn max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = Some(max_output_bytes);
+        self
+    }
+
+    /// Sets the max number of bytes of the source code accepted by the sandbox.
+    pub fn max_source_bytes(mut self, max_source_bytes: usize) -> Self {
+        self.max_source_bytes = Some(max_source_bytes);
+        self
+    }
+
+    /// Sets the environment variables that will be set within the container, by default none are
+    /// set.
+    pub fn env(mut self, env: &'a [(&'a str, &'a str)]) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Sets the user id that the code will be executed as within the container.
+    pub fn run_as_uid(mut self, run_as_uid: u32) -> Self {
+        self.run_as_uid = Some(run_as_uid);
+        self
+    }
+
+    /// Sets the path of the seccomp profile that will be applied to the container.
+    pub fn seccomp_profile(mut self, seccomp_profile: &'a Path) -> Self {
+        self.seccomp_profile = Some(seccomp_profile);
+        self
+    }
+
+    /// Sets if the request path should be kept once the request has been ran, by default it is not.
+    pub fn keep_artifacts(mut self, keep_artifacts: bool) -> Self {
+        self.keep_artifacts = keep_artifacts;
+        self
+    }
+
+    /// Sets if the execution of the tests should stop after the first failed test, by default all
+    /// the tests are executed.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Sets the max number of processes that can exist at once within the container.
+    pub fn pids_limit(mut self, pids_limit: u32) -> Self {
+        self.pids_limit = Some(pids_limit);
+        self
+    }
+
+    /// Sets the max number of bytes of the tmpfs mounted at /tmp within the container, by default
+    /// no tmpfs is mounted.
+    pub fn tmpfs_size(mut self, tmpfs_size: u64) -> Self {
+        self.tmpfs_size = Some(tmpfs_size);
+        self
+    }
+
+    /// Sets if ANSI escape sequences should be removed from the output, by default they are kept.
+    pub fn strip_ansi(mut self, strip_ansi: bool) -> Self {
+        self.strip_ansi = strip_ansi;
+        self
+    }
+
+    /// Sets if the standard error output should be merged into the standard output, by default
+    /// they are kept separate.
+    pub fn merge_output(mut self, merge_output: bool) -> Self {
+        self.merge_output = merge_output;
+        self
+    }
+
+    /// Sets the token that can be used to cancel the request while executing, by default the
+    /// request cannot be cancelled.
+    pub fn cancellation(mut self, cancellation: &'a CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Sets if the resource usage of the executed code should be measured with the time binary
+    /// within the container, by default it is not.
+    pub fn measure_resources(mut self, measure_resources: bool) -> Self {
+        self.measure_resources = measure_resources;
+        self
+    }
+
+    /// Sets the arguments that are given to the executed code, by default none are given.
+    pub fn program_args(mut self, program_args: &'a [&'a str]) -> Self {
+        self.program_args = program_args;
+        self
+    }
+
+    /// Builds the sandbox request, returning a invalid request error if any of the required fields
+    /// (id, path, source code and compiler) have not been provided. When the timeout has not been
+    /// provided the timeout is zero, which is replaced with the default timeout of the config the
+    /// request is ran with (and is otherwise rejected when the request is validated).
+    pub fn build(self) -> Result<SandboxRequest<'a>, SandboxError> {
+        let missing = |field: &str| SandboxError::InvalidRequest(format!("missing required field {}", field));
+
+        Ok(SandboxRequest {
+            id: self.id.ok_or_else(|| missing("id"))?,
+            timeout: self.timeout.unwrap_or_default(),
+            path: self.path.ok_or_else(|| missing("path"))?,
+            source_code: self.source_code.ok_or_else(|| missing("source code"))?,
+            compiler: self.compiler.ok_or_else(|| missing("compiler"))?,
+            tests: self.tests,
+            memory_limit: self.memory_limit,
+            cpu_limit: self.cpu_limit,
+            network_enabled: self.network_enabled,
+            max_output_bytes: self.max_output_bytes,
+            max_source_bytes: self.max_source_bytes,
+            env: self.env,
+            run_as_uid: self.run_as_uid,
+            seccomp_profile: self.seccomp_profile,
+            keep_artifacts: self.keep_artifacts,
+            fail_fast: self.fail_fast,
+            pids_limit: self.pids_limit,
+            tmpfs_size: self.tmpfs_size,
+            strip_ansi: self.strip_ansi,
+            merge_output: self.merge_output,
+            cancellation: self.cancellation,
+            measure_resources: self.measure_resources,
+            program_args: self.program_args,
+        })
+    }
+}
+
+/// A token used to cancel a request from outside of the sandbox (e.g when the user is no longer
+/// waiting for the result), the token is cloned to be shared between the threads that cancel and
+/// execute the request. Cancelling a token cancels every request it was given to.
+///
+/// # Example
+/// ```
+/// # use compileme::sandbox::CancellationToken;
+/// let token = CancellationToken::new();
+/// let handle = token.clone();
+///
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a new token which has not been cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancels the token, any requests executing with the token are stopped. Cancelling a token
+    /// which has already been cancelled has no effect.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// If the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Tokens are equal when they are clones of the same token, and so are cancelled together.
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ExecutionStage {
+    /// The source code was being compiled, this stage is only reached by compilers that are not
+    /// interpreters.
+    Compile,
+    /// The code (or the compiled output of the code) was being executed.
+    Run,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum TermReason {
+    /// The executed code exited by itself with the given exit code.
+    Exited(i32),
+    /// The executed code was terminated by the given signal (e.g 11 for SIGSEGV).
+    Signaled(i32),
+    /// The executed code ran for longer than the timeout and was killed.
+    TimedOut,
+    /// The executed code exceeded the memory limit of the request and was killed.
+    OutOfMemory,
+    /// The request was cancelled while the code was executing and the code was killed.
+    Cancelled,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct SandboxResponse {
+    /// The standard output that was written by the executed code, this is the content of the
+    /// compilers standard output file once the container has finished executing. When the request
+    /// merges the outputs this is the interleaved standard output and standard error output.
+    pub stdout: String,
+    /// The standard error output that was written by the executed code, this is the content of the
+    /// compilers standard error file once the container has finished executing.
+    pub stderr: String,
+    /// The raw bytes of the standard output that was written by the executed code, unlike the
+    /// standard output these bytes are not decoded so are exactly what the executed code wrote.
+    pub stdout_bytes: Vec<u8>,
+    /// The raw bytes of the standard error output that was written by the executed code.
+    pub stderr_bytes: Vec<u8>,
+    /// The exit code of the container that executed the code, this will be none if the container
+    /// was terminated without an exit code (e.g it was terminated by a signal).
+    pub exit_code: Option<i32>,
+    /// The signal that terminated the executed code, if the code was terminated by a signal rather
+    /// than exiting (e.g 11 for SIGSEGV).
+    pub signal: Option<i32>,
+    /// The results of the tests that have been executed with the request, with one result per test
+    /// that was provided on the request.
+    pub test_results: Vec<SandboxTestResponse>,
+    /// If the container executing the code was killed for exceeding the memory limit of the
+    /// request.
+    pub out_of_memory: bool,
+    /// The stage of the execution that the code reached, if the source code failed to compile then
+    /// this will be the compile stage and the standard error output will contain the output of the
+    /// compiler, otherwise this will be the run stage.
+    pub stage: ExecutionStage,
+    /// The wall clock duration of the execution of the container, this does not include the time
+    /// taken to prepare or clean up the sandbox environment.
+    pub duration: Duration,
+    /// The system time at which the container started executing the code, unlike the duration this
+    /// is not monotonic and is used to correlate the execution with external logs.
+    pub started_at: SystemTime,
+    /// The system time at which the container finished executing the code.
+    pub finished_at: SystemTime,
+    /// If the standard output or standard error output exceeded the max output bytes of the request
+    /// and was truncated before being read into the response.
+    pub truncated: bool,
+    /// The path of the directory containing the files written and produced for the request, this
+    /// is only set when the request keeps its artifacts, otherwise the directory has been removed.
+    pub artifacts_path: Option<PathBuf>,
+    /// The logs of the container that executed the code, this is the output of the container
+    /// itself rather than the output of the executed code (e.g diagnostics written by the script).
+    /// This is only set when the docker backend is capturing container logs.
+    pub container_logs: Option<String>,
+    /// The reason the executed code was terminated, describing if the code exited, was terminated
+    /// by a signal, ran for longer than the timeout or was killed for exceeding the memory limit.
+    pub term_reason: TermReason,
+    /// If the executed code failed to create a process (or thread) since the process limit of the
+    /// request was reached, this is read from the pids cgroup of the container by the script.
+    pub process_limit_reached: bool,
+    /// If the compiled output of the source code was restored from the compile cache of the backend
+    /// rather than compiling the source code again, this is only set for compilers which are not
+    /// interpreters when the backend has a compile cache.
+    pub compile_cache_hit: bool,
+    /// The peak memory usage of the container while executing the code in bytes, as reported by
+    /// the cgroup of the container. This is none when the usage is not available (e.g with the
+    /// local backend). With a warm backend this is the peak across all executions in the container.
+    pub max_rss_bytes: Option<u64>,
+    /// The cpu time used by the container while executing the code, as reported by the cgroup of
+    /// the container. This is none when the usage is not available, the same as the peak memory.
+    pub cpu_time: Option<Duration>,
+    /// The resource usage of the executed code measured by the time binary, this is only set when
+    /// the request is measuring resources and the output of the time binary could be parsed.
+    pub resource_usage: Option<ResourceUsage>,
+    /// The transcript of the output written and the input sent during a interactive execution,
+    /// this is empty unless the request was executed interactively.
+    pub transcript: Vec<TranscriptEntry>,
+}
+
+impl SandboxResponse {
+    /// Creates a new response from the output files of the given request, reading the standard
+    /// output file and the standard error output file from the prepared paths.
+    ///
+    /// # Arguments
+    /// * `request` - The sandbox request in which the output files are being read from.
+    /// * `paths` - The prepared paths of the files of the request.
+    /// * `container_exit` - The details of the exit of the container that executed the request.
+    pub(crate) fn from_request(request: &SandboxRequest, paths: &PreparedPaths, container_exit: ContainerExit) -> Result<SandboxResponse, io::Error> {
+        // The standard output is followed by the end of output line of the script, which does not
+        // count towards the max output bytes so is allowed for when reading the output.
+        let stdout_max_bytes = request.max_output_bytes.map(|max_bytes| max_bytes + END_OF_OUTPUT_MAX_BYTES);
+
+        let (mut stdout_bytes, mut stdout_truncated) = read_output(&paths.stdout_file, stdout_max_bytes)?;
+        let (stderr_bytes, stderr_truncated) = read_output(&paths.stderr_file, request.max_output_bytes)?;
+
+        // Remove the end of output marker written by the script, this is not part of the output
+        // of the executed code and would otherwise cause all tests to fail. The resource usage
+        // that follows the marker is read before it is removed.
+        let mut max_rss_bytes = None;
+        let mut cpu_time = None;
+        let mut process_limit_reached = false;
+
+        if let Some(index) = rfind_bytes(&stdout_bytes, END_OF_OUTPUT_MARKER) {
+            let usage = String::from_utf8_lossy(&stdout_bytes[index + END_OF_OUTPUT_MARKER.len()..]).into_owned();
+            let mut fields = usage.split_whitespace().skip(1);
+
+            max_rss_bytes = fields.next().and_then(|field| field.parse().ok());
+            cpu_time = fields.next().and_then(|field| field.parse().ok()).map(Duration::from_nanos);
+            process_limit_reached = fields.next().and_then(|field| field.parse::<u64>().ok()).is_some_and(|events| events > 0);
+
+            stdout_bytes.truncate(index);
         }
 
-        // Create the standard output file and standard error output file, these will be directed
-        // towards when the source code file is compiled or the interpreted file is executed.
-        let source_standard_out = self.request.path.join(self.request.compiler.standard_output_file);
-        let source_error_out = self.request.path.join(self.request.compiler.standard_error_file);
+        if let Some(max_bytes) = request.max_output_bytes.filter(|max_bytes| stdout_bytes.len() > *max_bytes) {
+            stdout_bytes.truncate(max_bytes);
+            stdout_truncated = true;
+        }
 
-        File::create(source_standard_out);
-        File::create(source_error_out);
+        // The status file is empty when the script never completed (e.g the container was killed),
+        // in which case the execution is treated as ending at the run stage.
+        let status = std::fs::read_to_string(&paths.status_file).unwrap_or_default();
+        let mut status_fields = status.split_whitespace();
 
-        // Finally copy in the script file that will be executed to execute the program.
-        let current_dir = env::current_dir()?;
+        let stage = match status_fields.next() {
+            Some(COMPILE_FAILED_STATUS) if !request.compiler.interpreter => ExecutionStage::Compile,
+            _ => ExecutionStage::Run,
+        };
 
-        std::fs::copy(current_dir.as_path().join(Path::new("/dockerFiles/source.sh")),
-                      self.request.path.join(Path::new("script.sh")));
+        // The usage file is empty unless the time binary wrote to it, in which case a failure to
+        // parse the usage only leaves the usage unknown rather than failing the response.
+        let resource_usage = if request.measure_resources {
+            std::fs::read_to_string(&paths.usage_file).ok().as_deref().and_then(ResourceUsage::parse)
+        } else {
+            None
+        };
 
-        Ok(())
+        let mut stdout = decode_output(&stdout_bytes, stdout_truncated);
+        let mut stderr = decode_output(&stderr_bytes, stderr_truncated);
+
+        if request.strip_ansi {
+            stdout = strip_ansi(&stdout);
+            stderr = strip_ansi(&stderr);
+        }
+
+        // The script reports the signal that terminated the executed code separately from the
+        // status code, since the shell reports both an exit code and a signal as the same status
+        // code. When the signal is not known (e.g the container was killed) a status code of 128
+        // plus the number of a signal is treated as the signal.
+        let (exit_code, signal) = match status_fields.next().map(|signal| (signal, signal.parse::<i32>())) {
+            Some((NO_SIGNAL_STATUS, _)) => (container_exit.status_code, None),
+            Some((_, Ok(signal))) => (None, Some(signal)),
+            _ => match container_exit.status_code {
+                Some(code) if code > 128 && code <= 128 + 64 => (None, Some(code - 128)),
+                code => (code, None),
+            },
+        };
+
+        // A container that is killed by the kernel for exceeding its memory limit is terminated
+        // with a SIGKILL and marked as out of memory by the runtime. When the container could not
+        // be inspected a SIGKILL is only treated as a memory limit kill if a memory limit was set.
+        let out_of_memory = container_exit.oom_killed
+            .unwrap_or_else(|| request.memory_limit.is_some() && signal == Some(9));
+
+        let term_reason = if container_exit.cancelled {
+            TermReason::Cancelled
+        } else if container_exit.timed_out {
+            TermReason::TimedOut
+        } else if out_of_memory {
+            TermReason::OutOfMemory
+        } else if let Some(signal) = signal {
+            TermReason::Signaled(signal)
+        } else {
+            TermReason::Exited(exit_code.unwrap_or(-1))
+        };
+
+        Ok(SandboxResponse {
+            stdout,
+            stderr,
+            stdout_bytes,
+            stderr_bytes,
+            exit_code,
+            signal,
+            test_results: Vec::new(),
+            out_of_memory,
+            stage,
+            duration: container_exit.duration,
+            started_at: container_exit.started_at,
+            finished_at: container_exit.finished_at,
+            truncated: stdout_truncated || stderr_truncated,
+            artifacts_path: None,
+            container_logs: container_exit.logs,
+            term_reason,
+            process_limit_reached,
+            compile_cache_hit: container_exit.compile_cache_hit,
+            max_rss_bytes,
+            cpu_time,
+            resource_usage,
+            transcript: Vec::new(),
+        })
+    }
+}
+
+/// Reads the output file at the given path, reading at most the given max number of bytes.
+/// Returning the read bytes of the output and if the output was truncated.
+///
+/// # Arguments
+/// * `path` - The path of the output file being read.
+/// * `max_bytes` - The max number of bytes that will be read, if any.
+fn read_output(path: &Path, max_bytes: Option<usize>) -> io::Result<(Vec<u8>, bool)> {
+    let mut output = Vec::new();
+    let mut file = File::open(path)?;
+
+    // When limited a single byte more than the limit is read, which is enough to know that the
+    // output was truncated without reading the rest of the file into memory.
+    let truncated = match max_bytes {
+        Some(max_bytes) => {
+            (&mut file).take(max_bytes as u64 + 1).read_to_end(&mut output)?;
+            let truncated = output.len() > max_bytes;
+
+            if truncated {
+                output.truncate(max_bytes);
+            }
+
+            truncated
+        }
+        None => {
+            file.read_to_end(&mut output)?;
+            false
+        }
+    };
+
+    Ok((output, truncated))
+}
+
+/// Decodes the given bytes of output into a string, the executed code can write anything to its
+/// output so any invalid UTF-8 is replaced rather than failing to decode the output. When the
+/// output was truncated in the middle of a character the incomplete character is dropped.
+///
+/// # Arguments
+/// * `output` - The bytes of the output being decoded.
+/// * `truncated` - If the output was truncated.
+fn decode_output(mut output: &[u8], truncated: bool) -> String {
+    if truncated {
+        if let Err(error) = std::str::from_utf8(output) {
+            if error.error_len().is_none() {
+                output = &output[..error.valid_up_to()];
+            }
+        }
+    }
+
+    String::from_utf8_lossy(output).into_owned()
+}
+
+/// Removes the ANSI escape sequences from the given output, control sequences (e.g colors and
+/// cursor movement), operating system commands (e.g window titles) and any other escaped character.
+///
+/// # Arguments
+/// * `output` - The output the escape sequences are being removed from.
+fn strip_ansi(output: &str) -> String {
+    let mut stripped = String::with_capacity(output.len());
+    let mut characters = output.chars().peekable();
+
+    while let Some(character) = characters.next() {
+        if character != '\u{1b}' {
+            stripped.push(character);
+            continue;
+        }
+
+        match characters.next() {
+            // A control sequence is ended by its final character, after any parameters and
+            // intermediate characters.
+            Some('[') => {
+                for character in characters.by_ref() {
+                    if ('\u{40}'..='\u{7e}').contains(&character) {
+                        break;
+                    }
+                }
+            }
+            // A operating system command is ended by a bell or the string terminator (ESC \).
+            Some(']') => {
+                while let Some(character) = characters.next() {
+                    if character == '\u{7}' || (character == '\u{1b}' && characters.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    stripped
+}
+
+/// Finds the index of the last occurrence of the given marker within the given bytes of output.
+///
+/// # Arguments
+/// * `output` - The bytes of the output being searched.
+/// * `marker` - The marker being searched for.
+fn rfind_bytes(output: &[u8], marker: &str) -> Option<usize> {
+    output.windows(marker.len()).rposition(|window| window == marker.as_bytes())
+}
+
+/// Finds the index of the first occurrence of the given text within the given bytes of output.
+///
+/// # Arguments
+/// * `output` - The bytes of the output being searched.
+/// * `text` - The text being searched for.
+fn find_bytes(output: &[u8], text: &str) -> Option<usize> {
+    (0..=output.len().saturating_sub(text.len())).find(|&index| output[index..].starts_with(text.as_bytes()))
+}
+
+/// Replaces the file at the given path with a named pipe, the pipe is readable and writable by any
+/// user since the code is not executed as the owner of the pipe.
+///
+/// # Arguments
+/// * `path` - The path of the file being replaced.
+fn create_pipe(path: &Path) -> io::Result<()> {
+    std::fs::remove_file(path)?;
+
+    let status = Command::new("mkfifo").args(["-m", "666"]).arg(path).stdin(Stdio::null()).status()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!("failed to create the pipe {}", path.display())));
+    }
+
+    Ok(())
+}
+
+/// The paths of the files within the request path that are written when the sandbox environment
+/// is prepared, these are the files which are mounted into the container and read back once the
+/// container has exited.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PreparedPaths {
+    /// The canonical path of the request directory that is mounted into the container.
+    pub directory: PathBuf,
+    /// The path of the source code file.
+    pub source_file: PathBuf,
+    /// The path of the script that is executed within the container.
+    pub script_file: PathBuf,
+    /// The path of the standard input file that is directed into the executed code.
+    pub stdin_file: PathBuf,
+    /// The path of the file the standard output of the executed code is written to.
+    pub stdout_file: PathBuf,
+    /// The path of the file the standard error output of the executed code is written to.
+    pub stderr_file: PathBuf,
+    /// The path of the file the resource usage of the executed code is written to, when measuring.
+    pub usage_file: PathBuf,
+    /// The path of the file the status of the execution is written to once completed.
+    pub status_file: PathBuf,
+}
+
+impl PreparedPaths {
+    /// Creates the paths of the files of the given compiler within the given request directory.
+    ///
+    /// # Arguments
+    /// * `directory` - The canonical path of the request directory.
+    /// * `compiler` - The compiler the files are being written for.
+    fn new(directory: PathBuf, compiler: &LanguageCompiler) -> PreparedPaths {
+        PreparedPaths {
+            source_file: directory.join(compiler.source_file_name()),
+            script_file: directory.join(SCRIPT_FILE),
+            stdin_file: directory.join(STANDARD_INPUT_FILE),
+            stdout_file: directory.join(compiler.standard_output_file),
+            stderr_file: directory.join(compiler.standard_error_file),
+            usage_file: directory.join(RESOURCE_USAGE_FILE),
+            status_file: directory.join(STATUS_FILE),
+            directory,
+        }
+    }
+}
+
+/// The result of a dry run of a request, the paths of the prepared files and the command that would
+/// have executed the request.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct DryRun {
+    /// The paths of the files that were written when the request was prepared.
+    pub paths: PreparedPaths,
+    /// The command the backend would have executed, the binary followed by each of the arguments.
+    pub command: Vec<String>,
+}
+
+/// The details of the exit of a container once it has completed executing.
+#[derive(Debug, Clone)]
+pub(crate) struct ContainerExit {
+    /// The status code the container exited with, if any.
+    pub(crate) status_code: Option<i32>,
+    /// The wall clock duration the container was executing for.
+    pub(crate) duration: Duration,
+    /// The time at which the container started executing.
+    pub(crate) started_at: SystemTime,
+    /// The time at which the container finished executing.
+    pub(crate) finished_at: SystemTime,
+    /// If the container was killed for running longer than its timeout.
+    pub(crate) timed_out: bool,
+    /// If the container was killed since the request was cancelled.
+    pub(crate) cancelled: bool,
+    /// The logs of the container, if the logs were captured before the container was removed.
+    pub(crate) logs: Option<String>,
+    /// If the container was killed for exceeding its memory limit, if known by the backend.
+    pub(crate) oom_killed: Option<bool>,
+    /// If the compiled output was restored from the compile cache rather than compiled.
+    pub(crate) compile_cache_hit: bool,
+}
+
+#[derive(Debug)]
+pub enum SandboxError {
+    /// A io error occurred while preparing the sandbox environment or while reading back the
+    /// output of the executed code.
+    Io(io::Error),
+    /// The container executing the code ran for longer than the timeout of the request and was
+    /// terminated before completing.
+    Timeout,
+    /// The container failed to execute the code, containing the exit code of the container.
+    ContainerFailed(i32),
+    /// The request provided to the sandbox was not valid for execution, containing a description
+    /// of the reason the request was rejected.
+    InvalidRequest(String),
+    /// The container runtime is not installed or is not ready to execute containers, containing a
+    /// description of the reason the runtime is not available.
+    RuntimeUnavailable(String),
+    /// The request was cancelled with its cancellation token before completing.
+    Cancelled,
+    /// The image of the compiler does not exist locally and could not be pulled, containing the
+    /// name of the image that was not found.
+    ImageNotFound(String),
+    /// Running the request failed while the request kept its artifacts, containing the path of the
+    /// kept directory and the error that caused the failure (which is also the source).
+    ArtifactsKept(PathBuf, Box<SandboxError>),
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxError::Io(error) => write!(f, "sandbox io error: {}", error),
+            SandboxError::Timeout => write!(f, "sandbox container exceeded the timeout"),
+            SandboxError::ContainerFailed(code) => write!(f, "sandbox container failed with exit code {}", code),
+            SandboxError::InvalidRequest(reason) => write!(f, "invalid sandbox request: {}", reason),
+            SandboxError::RuntimeUnavailable(reason) => write!(f, "container runtime unavailable: {}", reason),
+            SandboxError::Cancelled => write!(f, "sandbox request was cancelled"),
+            SandboxError::ImageNotFound(image) => write!(f, "sandbox image {} was not found", image),
+            SandboxError::ArtifactsKept(path, error) => write!(f, "{} (artifacts kept at {})", error, path.display()),
+        }
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for SandboxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SandboxError::Io(error) => Some(error),
+            SandboxError::ArtifactsKept(_, error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SandboxError {
+    fn from(error: io::Error) -> Self {
+        SandboxError::Io(error)
+    }
+}
+
+/// The defaults shared between many requests, so that the runtime and the limits do not need to be
+/// repeated on every request. The fields of the request override the defaults of the config, the
+/// defaults only apply to the fields the request has not provided.
+///
+/// # Example
+/// ```
+/// # use compileme::sandbox::{SandboxConfig, SandboxRequest, COMPILERS};
+/// # use std::path::Path;
+/// # use std::time::Duration;
+/// let config = SandboxConfig {
+///     timeout: Duration::from_secs(5),
+///     memory_limit: Some(256 * 1024 * 1024),
+///     ..Default::default()
+/// };
+///
+/// let request = SandboxRequest::builder()
+///     .id("test")
+///     .path(Path::new("./temp/config"))
+///     .source("print('hello')")
+///     .compiler(COMPILERS[0])
+///     .memory_limit(64 * 1024 * 1024)
+///     .build()
+///     .unwrap();
+///
+/// let request = config.apply(&request);
+///
+/// assert_eq!(request.timeout, Duration::from_secs(5));
+/// assert_eq!(request.memory_limit, Some(64 * 1024 * 1024));
+/// ```
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub struct SandboxConfig<'a> {
+    /// The container runtime used to execute the requests.
+    pub runtime: ContainerRuntime,
+    /// The path of the binary executed for the container runtime, when not provided the binary of
+    /// the runtime is found on the path.
+    pub runtime_binary: Option<&'a str>,
+    /// The timeout of requests which have not provided a timeout.
+    pub timeout: Duration,
+    /// The memory limit of requests which have not provided a memory limit.
+    pub memory_limit: Option<u64>,
+    /// The cpu limit of requests which have not provided a cpu limit.
+    pub cpu_limit: Option<f64>,
+    /// The process limit of requests which have not provided a process limit.
+    pub pids_limit: Option<u32>,
+    /// The max output bytes of requests which have not provided a max.
+    pub max_output_bytes: Option<usize>,
+    /// The max source bytes of requests which have not provided a max.
+    pub max_source_bytes: Option<usize>,
+    /// The root directory that the path of every request must be within, when not provided the
+    /// path must be within the temp directory under the current directory or the system temp
+    /// directory.
+    pub temp_root: Option<&'a Path>,
+}
+
+impl<'a> SandboxConfig<'a> {
+    /// Applies the defaults of the config to the given request, returning a copy of the request
+    /// with each field the request has not provided replaced by the default of the config.
+    ///
+    /// # Arguments
+    /// * `request` - The request the defaults are being applied to.
+    pub fn apply(&self, request: &SandboxRequest<'a>) -> SandboxRequest<'a> {
+        SandboxRequest {
+            timeout: if request.timeout.is_zero() { self.timeout } else { request.timeout },
+            memory_limit: request.memory_limit.or(self.memory_limit),
+            cpu_limit: request.cpu_limit.or(self.cpu_limit),
+            pids_limit: request.pids_limit.or(self.pids_limit),
+            max_output_bytes: request.max_output_bytes.or(self.max_output_bytes),
+            max_source_bytes: request.max_source_bytes.or(self.max_source_bytes),
+            ..*request
+        }
+    }
+}
+
+pub struct Sandbox<'a> {
+    request: SandboxRequest<'a>,
+    backend: Box<dyn ExecutionBackend + 'a>,
+    script_path: &'a Path,
+    allowed_root: Option<&'a Path>,
+}
+
+impl<'a> Sandbox<'a> {
+    /// Creates a new instance of the sandbox, the entry point for the container creator, management
+    /// and completion. Taking a request object that will contain all the related information for
+    /// creating the container.
+    ///
+    /// # Arguments
+    /// * `request` - The sandbox request containing the required data to crate the container.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use compileme::sandbox;
+    /// # use compileme::sandbox::SandboxRequest;
+    /// # use std::path::{Component, Path, PathBuf};
+    /// # use std::time::{Duration, Instant};
+    /// let request = SandboxRequest::builder()
+    ///   .id("1234")
+    ///   .timeout(Duration::from_secs(20))
+    ///   .path(Path::new("./temp/random/python_test/"))
+    ///   .source("print('hello')")
+    ///   .compiler(&sandbox::COMPILERS[0])
+    ///   .build()
+    ///   .unwrap();
+    ///
+    /// let sandbox = sandbox::Sandbox::new(&request);
+    /// ```
+    pub fn new(request: &'a SandboxRequest<'a>) -> Sandbox<'a> {
+        Sandbox {
+            request: *request,
+            backend: Box::new(DockerBackend::default()),
+            script_path: Path::new(DEFAULT_SCRIPT_PATH),
+            allowed_root: None,
+        }
+    }
+
+    /// Creates a new instance of the sandbox for the given request with the defaults of the given
+    /// config applied, executing the request with the container runtime of the config and only
+    /// allowing the request path within the temp root of the config (if any).
+    ///
+    /// # Arguments
+    /// * `config` - The config containing the defaults of the request.
+    /// * `request` - The sandbox request the defaults are applied to.
+    pub fn with_config(config: &SandboxConfig<'a>, request: &'a SandboxRequest<'a>) -> Sandbox<'a> {
+        let mut backend = DockerBackend::new().with_runtime(config.runtime);
+
+        if let Some(runtime_binary) = config.runtime_binary {
+            backend = backend.with_runtime_binary(runtime_binary);
+        }
+
+        Sandbox {
+            request: config.apply(request),
+            backend: Box::new(backend),
+            script_path: Path::new(DEFAULT_SCRIPT_PATH),
+            allowed_root: config.temp_root,
+        }
+    }
+
+    /// Sets the backend that will be used to execute the request, by default this is the docker
+    /// backend.
+    ///
+    /// # Arguments
+    /// * `backend` - The backend that will be used to execute the request.
+    pub fn with_backend(mut self, backend: impl ExecutionBackend + 'a) -> Self {
+        self.backend = Box::new(backend);
+        self
+    }
+
+    /// Sets the path of the script that will be copied into the request path and executed within
+    /// the container, by default this is the script within the docker files directory. A relative
+    /// path is resolved under the current directory.
+    ///
+    /// # Arguments
+    /// * `script_path` - The path of the script that will be executed within the container.
+    pub fn with_script_path(mut self, script_path: &'a Path) -> Self {
+        self.script_path = script_path;
+        self
+    }
+
+    /// Sets the root directory that the request path must be within, by default the request path
+    /// must be within the temp directory under the current directory or the system temp directory.
+    ///
+    /// # Arguments
+    /// * `allowed_root` - The root directory that the request path must be within.
+    pub fn with_allowed_root(mut self, allowed_root: &'a Path) -> Self {
+        self.allowed_root = Some(allowed_root);
+        self
+    }
+
+    /// Prepare the sandbox environment for execution, creates the temp file locations, writes down
+    /// the source code file and ensures that all properties are correct and valid for execution.
+    /// If all is prepared properly, the paths of the prepared files are returned which are then
+    /// used to execute the request.
+    pub fn prepare(&mut self) -> Result<PreparedPaths, SandboxError> {
+        self.request.validate()?;
+        self.validate_path()?;
+
+        if let Some(seccomp_profile) = self.request.seccomp_profile.filter(|profile| !profile.is_file()) {
+            return Err(SandboxError::InvalidRequest(
+                format!("seccomp profile {} does not exist", seccomp_profile.display())));
+        }
+
+        // Create the temporary directory that will be used for storing the source code, standard
+        // input and then the location in which the compiler will write the standard output and the
+        // standard error output. After the data is written and returned, the location will be
+        // deleted.
+        std::fs::create_dir_all(self.request.path)?;
+
+        // Validate again now the directory exists, ensuring no symbolic link within the path has
+        // resolved the directory to outside of the allowed root.
+        self.validate_path()?;
+
+        let paths = PreparedPaths::new(std::fs::canonicalize(self.request.path)?, self.request.compiler);
+
+        // Go through the process of writing down the source file to disk, this will be used
+        // and read again when gathering the results.
+        write_synced(&paths.source_file, self.request.source_code.as_bytes())?;
+
+        // Finally copy in the script file that will be executed to execute the program. A relative
+        // script path is resolved under the current directory.
+        let script_path = env::current_dir()?.join(self.script_path);
+
+        if !script_path.is_file() {
+            return Err(SandboxError::Io(io::Error::new(io::ErrorKind::NotFound,
+                format!("sandbox script not found at {}", script_path.display()))));
+        }
+
+        std::fs::copy(script_path, &paths.script_file)?;
+
+        // Create the empty standard input and output files, these are written again before each of
+        // the tests but ensure every prepared path exists once prepared.
+        self.prepare_test(&paths, None)?;
+
+        Ok(paths)
+    }
+
+    /// Prepare the sandbox environment for the execution of a single test, creating empty output
+    /// files and writing down the standard input of the test. This is performed before each test
+    /// so that the output of a previous test is never read as the output of the next.
+    ///
+    /// # Arguments
+    /// * `paths` - The prepared paths of the files of the request.
+    /// * `test` - The test that is about to be executed, if any.
+    fn prepare_test(&self, paths: &PreparedPaths, test: Option<&SandboxTest>) -> Result<(), SandboxError> {
+        // Create the standard output file and standard error output file, these will be directed
+        // towards when the source code file is compiled or the interpreted file is executed. The
+        // code is not executed as the owner of the files, so the output files must be writable
+        // by any user for the code to be able to write its output.
+        for output_path in [&paths.stdout_file, &paths.stderr_file, &paths.usage_file, &paths.status_file] {
+            let output_file = File::create(output_path)?;
+
+            #[cfg(unix)]
+            output_file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666))?;
+        }
+
+        // Write down the standard input file that will be directed into the executed code, when
+        // there is no test (or the test has no standard input) the file is left empty.
+        let stdin_data = test
+            .and_then(|test| test.stdin_data)
+            .map(|stdin_data| stdin_contents(stdin_data))
+            .unwrap_or_default();
+
+        write_synced(&paths.stdin_file, stdin_data.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Runs the given request from start to finish, ensuring the compiler image is available,
+    /// preparing the sandbox environment, executing the request and then cleaning up the
+    /// environment. An error at any stage stops the run, but the environment is still cleaned up.
+    /// When the request keeps its artifacts the environment is not cleaned up and the path of the
+    /// environment is returned on the response.
+    pub fn run(&mut self) -> Result<SandboxResponse, SandboxError> {
+        self.run_with(|sandbox, paths| sandbox.execute(paths))
+    }
+
+    /// Runs the given request the same as run, but executing the request with execute streaming so
+    /// that each complete line of output is passed to the related callback as it is written.
+    ///
+    /// # Arguments
+    /// * `on_stdout_line` - The callback invoked with each line of the standard output.
+    /// * `on_stderr_line` - The callback invoked with each line of the standard error output.
+    pub fn run_streaming(&mut self,
+                         on_stdout_line: impl FnMut(&str),
+                         on_stderr_line: impl FnMut(&str)) -> Result<SandboxResponse, SandboxError> {
+        self.run_with(|sandbox, paths| sandbox.execute_streaming(paths, on_stdout_line, on_stderr_line))
+    }
+
+    /// Runs the given request the same as run, but executing the request with execute interactive
+    /// so that the standard input of the code is driven by the given steps.
+    ///
+    /// # Arguments
+    /// * `steps` - The steps that drive the standard input of the executed code.
+    pub fn run_interactive(&mut self, steps: &[InteractiveStep]) -> Result<SandboxResponse, SandboxError> {
+        self.run_with(|sandbox, paths| sandbox.execute_interactive(paths, steps))
+    }
+
+    /// Ensures the compiler image is available, prepares the sandbox environment, executes the
+    /// request with the given executor and then cleans up the environment. The environment is
+    /// cleaned up even when preparing or executing fails, in which case that error is returned. When
+    /// the request keeps its artifacts the error is instead returned within artifacts kept.
+    ///
+    /// # Arguments
+    /// * `execute` - The executor used to execute the prepared request with the prepared paths.
+    fn run_with(&mut self, execute: impl FnOnce(&mut Self, &PreparedPaths) -> Result<SandboxResponse, SandboxError>) -> Result<SandboxResponse, SandboxError> {
+        self.request.validate()?;
+        self.backend.ensure_available(&self.request)?;
+
+        // The guard removes the request path if preparing or executing panics, otherwise it is
+        // disarmed and the path is removed by cleanup so that a failure to remove it is reported.
+        self.validate_path()?;
+        let mut guard = DirectoryGuard::new(self.request.path);
+
+        let response = self.prepare().and_then(|paths| execute(self, &paths));
+
+        guard.disarm();
+
+        if self.request.keep_artifacts {
+            // The kept directory is reported on failure too, since that is when it is inspected.
+            let artifacts_path = std::fs::canonicalize(self.request.path);
+
+            return match response {
+                Ok(response) => Ok(SandboxResponse { artifacts_path: Some(artifacts_path?), ..response }),
+                Err(error) => {
+                    let artifacts_path = artifacts_path.unwrap_or_else(|_| self.request.path.to_path_buf());
+                    Err(SandboxError::ArtifactsKept(artifacts_path, Box::new(error)))
+                }
+            };
+        }
+
+        let cleanup = self.cleanup();
+
+        let response = response?;
+        cleanup?;
+
+        Ok(response)
+    }
+
+    /// Executes the given request with the backend of the sandbox (by default within the docker
+    /// container of the related compiler), the sandbox environment must have already been prepared.
+    /// The request is executed once per test, once each execution has completed the standard output
+    /// and standard error output files are read back into the response. The source code is only
+    /// written once and shared between all the tests.
+    ///
+    /// # Arguments
+    /// * `paths` - The paths returned when the sandbox environment was prepared.
+    pub fn execute(&mut self, paths: &PreparedPaths) -> Result<SandboxResponse, SandboxError> {
+        self.execute_tests(paths, |sandbox, timeout| sandbox.backend.run(paths, &sandbox.request, timeout))
+    }
+
+    /// Executes the given request the same as execute, but while the backend is running the
+    /// standard output and standard error output are read as they are written. Each complete line
+    /// is passed to the related callback, the full output is still collected into the response.
+    ///
+    /// # Arguments
+    /// * `paths` - The paths returned when the sandbox environment was prepared.
+    /// * `on_stdout_line` - The callback invoked with each line of the standard output.
+    /// * `on_stderr_line` - The callback invoked with each line of the standard error output.
+    pub fn execute_streaming(&mut self,
+                             paths: &PreparedPaths,
+                             mut on_stdout_line: impl FnMut(&str),
+                             mut on_stderr_line: impl FnMut(&str)) -> Result<SandboxResponse, SandboxError> {
+        self.execute_tests(paths, |sandbox, timeout| {
+            sandbox.run_backend_streaming(paths, timeout, &mut on_stdout_line, &mut on_stderr_line)
+        })
+    }
+
+    /// Executes the given request a single time (ignoring the tests of the request) while driving
+    /// the standard input of the code with the given steps, recording the transcript of the output
+    /// and the input on the response. The standard input file is replaced with a named pipe and the
+    /// standard output file is followed while the backend is running, once the output contains the
+    /// expected text of the next step the line of the step is sent. The standard input is closed
+    /// once every step has been sent, or once the timeout has passed without the expected text
+    /// being written. The executed code must flush its output for each prompt to be seen (e.g
+    /// PYTHONUNBUFFERED for python). A timed out execution is returned with its transcript.
+    ///
+    /// # Arguments
+    /// * `paths` - The paths returned when the sandbox environment was prepared.
+    /// * `steps` - The steps that drive the standard input of the executed code.
+    ///
+    /// # Example
+    /// ```
+    /// # use compileme::backend::LocalBackend;
+    /// # use compileme::registry::register_compiler;
+    /// # use compileme::sandbox::{InteractiveStep, LanguageCompiler, Sandbox, SandboxRequest, TranscriptEntry, COMPILERS};
+    /// # use std::time::Duration;
+    /// static SHELL: LanguageCompiler = LanguageCompiler {
+    ///     language: "shell",
+    ///     compiler: "sh",
+    ///     source_extension: "sh",
+    ///     ..*COMPILERS[0]
+    /// };
+    ///
+    /// register_compiler(&SHELL);
+    ///
+    /// let source = "printf 'name? '; read name; printf 'age? '; read age; echo \"$name is $age\"";
+    /// let path = SandboxRequest::temp_path("interactive").unwrap();
+    ///
+    /// let request = SandboxRequest::builder()
+    ///     .id("interactive")
+    ///     .timeout(Duration::from_secs(20))
+    ///     .path(&path)
+    ///     .source(source)
+    ///     .compiler(&SHELL)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let steps = [
+    ///     InteractiveStep { expect: "name? ", send: "alice" },
+    ///     InteractiveStep { expect: "age? ", send: "30" },
+    /// ];
+    ///
+    /// let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run_interactive(&steps).unwrap();
+    ///
+    /// assert_eq!(response.transcript, vec![
+    ///     TranscriptEntry::Output("name? ".to_string()),
+    ///     TranscriptEntry::Input("alice".to_string()),
+    ///     TranscriptEntry::Output("age? ".to_string()),
+    ///     TranscriptEntry::Input("30".to_string()),
+    ///     TranscriptEntry::Output("alice is 30\n".to_string()),
+    /// ]);
+    /// ```
+    pub fn execute_interactive(&mut self, paths: &PreparedPaths, steps: &[InteractiveStep]) -> Result<SandboxResponse, SandboxError> {
+        if self.request.cancellation.is_some_and(CancellationToken::is_cancelled) {
+            return Err(SandboxError::Cancelled);
+        }
+
+        self.prepare_test(paths, None)?;
+        create_pipe(&paths.stdin_file)?;
+
+        // Opening the pipe for both reading and writing does not wait for the executed code to
+        // open the pipe, and writing never fails when the code has already closed the pipe.
+        let stdin = OpenOptions::new().read(true).write(true).open(&paths.stdin_file)?;
+        let mut stdout = OutputFollower::open(&paths.stdout_file)?;
+        let deadline = Instant::now() + self.request.timeout;
+
+        let mut transcript = Vec::new();
+
+        let (driven, response) = thread::scope(|scope| {
+            let execution = scope.spawn(|| self.backend.run(paths, &self.request, self.request.timeout));
+            let driven = drive_steps(steps, &stdin, &mut stdout, &mut transcript, || execution.is_finished(), deadline);
+
+            // Closing the pipe gives the executed code the end of its standard input, this must
+            // happen before waiting for the execution otherwise the code could wait forever.
+            drop(stdin);
+
+            (driven, execution.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+        });
+
+        let response = response?;
+        let consumed = driven?;
+
+        if response.term_reason == TermReason::Cancelled {
+            return Err(SandboxError::Cancelled);
+        }
+
+        // The remainder of the output is taken from the response rather than the followed file,
+        // since the response no longer contains the markers written by the script.
+        if let Some(remaining) = response.stdout_bytes.get(consumed..).filter(|remaining| !remaining.is_empty()) {
+            transcript.push(TranscriptEntry::Output(String::from_utf8_lossy(remaining).into_owned()));
+        }
+
+        Ok(SandboxResponse { transcript, ..response })
+    }
+
+    /// The command that the backend of the sandbox would execute for the prepared request, the
+    /// binary followed by each of the arguments, without executing anything. For the docker backend
+    /// this is the invocation of the container runtime, including the limits, the mounts, the image
+    /// and the script. A backend without a command to inspect returns a empty command.
+    ///
+    /// # Arguments
+    /// * `paths` - The paths returned when the sandbox environment was prepared.
+    ///
+    /// # Example
+    /// ```
+    /// # use compileme::sandbox::{self, Sandbox, SandboxRequest};
+    /// # use std::time::Duration;
+    /// let path = SandboxRequest::temp_path("command").unwrap();
+    ///
+    /// let request = SandboxRequest::builder()
+    ///     .id("command")
+    ///     .timeout(Duration::from_secs(20))
+    ///     .path(&path)
+    ///     .source("print('hello')")
+    ///     .compiler(sandbox::compiler_for("python").unwrap())
+    ///     .memory_limit(64 * 1024 * 1024)
+    ///     .pids_limit(32)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let mut sandbox = Sandbox::new(&request);
+    /// let paths = sandbox.prepare().unwrap();
+    /// let command = sandbox.build_command(&paths).unwrap();
+    /// sandbox.cleanup().unwrap();
+    ///
+    /// let flag = |name: &str, value: &str| command.windows(2).any(|pair| pair[0] == name && pair[1] == value);
+    ///
+    /// assert_eq!(command[..2], ["docker", "run"]);
+    /// assert!(flag("--memory", "67108864b"));
+    /// assert!(flag("--pids-limit", "32"));
+    /// assert!(flag("--network", "none"));
+    /// assert!(flag("python_virtual_machine", "sh"));
+    ///
+    /// let request = SandboxRequest { network_enabled: true, tmpfs_size: Some(1024), ..request };
+    /// let mut sandbox = Sandbox::new(&request);
+    /// let paths = sandbox.prepare().unwrap();
+    /// let command = sandbox.build_command(&paths).unwrap();
+    /// sandbox.cleanup().unwrap();
+    ///
+    /// assert!(!command.iter().any(|arg| arg == "--network"));
+    /// assert!(command.iter().any(|arg| arg == "/tmp:rw,exec,nosuid,size=1024"));
+    /// ```
+    pub fn build_command(&self, paths: &PreparedPaths) -> Result<Vec<String>, SandboxError> {
+        self.backend.build_command(paths, &self.request)
+    }
+
+    /// Prepares the sandbox environment and builds the command of the request without executing
+    /// anything, this is used to check the layout of the prepared files and the command without
+    /// running any containers. The standard input of the first test (if any) is written the same as
+    /// before the first execution. The environment is not cleaned up, it is left in place to be
+    /// inspected and must be cleaned up by the caller.
+    ///
+    /// # Example
+    /// ```
+    /// # use compileme::testing::MockBackend;
+    /// # use compileme::sandbox::{self, Sandbox, SandboxRequest};
+    /// # use std::time::Duration;
+    /// let path = SandboxRequest::temp_path("dry").unwrap();
+    ///
+    /// let request = SandboxRequest::builder()
+    ///     .id("dry")
+    ///     .timeout(Duration::from_secs(20))
+    ///     .path(&path)
+    ///     .source("print('hello')")
+    ///     .compiler(sandbox::compiler_for("python").unwrap())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let backend = MockBackend::new();
+    /// let mut sandbox = Sandbox::new(&request).with_backend(&backend);
+    /// let dry_run = sandbox.dry_run().unwrap();
+    ///
+    /// assert!(dry_run.paths.source_file.is_file());
+    /// assert!(dry_run.paths.script_file.is_file());
+    /// assert!(backend.executions().is_empty());
+    ///
+    /// sandbox.cleanup().unwrap();
+    /// assert!(!path.exists());
+    /// ```
+    pub fn dry_run(&mut self) -> Result<DryRun, SandboxError> {
+        let paths = self.prepare()?;
+        self.prepare_test(&paths, self.request.tests.first())?;
+
+        let command = self.build_command(&paths)?;
+        Ok(DryRun { paths, command })
+    }
+
+    /// Executes each of the tests of the request against the prepared source code, collecting the
+    /// result of each test into the response. When the request has no tests the code is executed a
+    /// single time with empty standard input. The output of the response is the output of the last
+    /// executed test. Each test is executed with its own timeout (or the timeout of the request),
+    /// a test that times out is marked as timed out and the remaining tests are still executed
+    /// unless the request is failing fast.
+    ///
+    /// # Arguments
+    /// * `paths` - The prepared paths of the files of the request.
+    /// * `run` - The runner used to execute the request for each of the tests with a timeout.
+    fn execute_tests(&self, paths: &PreparedPaths, mut run: impl FnMut(&Self, Duration) -> Result<SandboxResponse, SandboxError>) -> Result<SandboxResponse, SandboxError> {
+        let tests: Vec<Option<&SandboxTest>> = if self.request.tests.is_empty() {
+            vec![None]
+        } else {
+            self.request.tests.iter().map(Some).collect()
+        };
+
+        let mut test_results = Vec::with_capacity(self.request.tests.len());
+        let mut last_response = None;
+
+        for test in tests {
+            if self.request.cancellation.is_some_and(CancellationToken::is_cancelled) {
+                return Err(SandboxError::Cancelled);
+            }
+
+            self.prepare_test(paths, test)?;
+
+            let timeout = test.and_then(|test| test.timeout).unwrap_or(self.request.timeout);
+            let response = run(self, timeout)?;
+            let timed_out = response.term_reason == TermReason::TimedOut;
+
+            if response.term_reason == TermReason::Cancelled {
+                return Err(SandboxError::Cancelled);
+            }
+
+            // Without any tests there is nothing else to execute, so the timeout is the result
+            // of the request rather than the result of a single test.
+            if timed_out && test.is_none() {
+                return Err(SandboxError::Timeout);
+            }
+
+            if let Some(test) = test {
+                test_results.push(if timed_out {
+                    test.timed_out()
+                } else {
+                    test.evaluate(&response)
+                });
+            }
+
+            // If the source code failed to compile then it will fail for every test, so there is no
+            // reason to execute any more. When failing fast the first failed test also stops the
+            // execution. The remaining tests are marked as not being ran.
+            let compilation_failed = response.stage == ExecutionStage::Compile;
+            let failed_fast = self.request.fail_fast
+                && test_results.last().map(|result| result.result) == Some(SandboxTestResult::Failed);
+
+            last_response = Some(response);
+
+            if compilation_failed || failed_fast {
+                test_results.extend(self.request.tests[test_results.len()..].iter().map(SandboxTest::not_ran));
+                break;
+            }
+        }
+
+        let response = last_response.ok_or_else(|| SandboxError::InvalidRequest("no code was executed".to_string()))?;
+        Ok(SandboxResponse { test_results, ..response })
+    }
+
+    /// Runs the request with the backend, but while the backend is running the request the output
+    /// files are followed and each complete line that is written is passed to the related callback.
+    ///
+    /// # Arguments
+    /// * `paths` - The prepared paths of the files of the request.
+    /// * `timeout` - The max amount of time the backend is allowed to run the request for.
+    /// * `on_stdout_line` - The callback invoked with each line of the standard output.
+    /// * `on_stderr_line` - The callback invoked with each line of the standard error output.
+    fn run_backend_streaming(&self,
+                             paths: &PreparedPaths,
+                             timeout: Duration,
+                             on_stdout_line: &mut dyn FnMut(&str),
+                             on_stderr_line: &mut dyn FnMut(&str)) -> Result<SandboxResponse, SandboxError> {
+        let mut stdout = OutputFollower::open(&paths.stdout_file)?;
+        let mut stderr = OutputFollower::open(&paths.stderr_file)?;
+
+        thread::scope(|scope| {
+            let execution = scope.spawn(|| self.backend.run(paths, &self.request, timeout));
+
+            // Keep reading the output until the execution has finished, the completion is checked
+            // before reading so that the last of the output is always read after the execution.
+            loop {
+                let finished = execution.is_finished();
+
+                stdout.read_lines(on_stdout_line)?;
+                stderr.read_lines(on_stderr_line)?;
+
+                if finished {
+                    break;
+                }
+
+                thread::sleep(Duration::from_millis(50));
+            }
+
+            stdout.finish(on_stdout_line);
+            stderr.finish(on_stderr_line);
+
+            execution.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    /// Cleans up the sandbox environment after execution, removing the request path and all the
+    /// files that were written into it. If the path has already been removed then this will not be
+    /// treated as a error. The path is validated before removal and is never removed if invalid.
+    pub fn cleanup(&self) -> Result<(), SandboxError> {
+        self.validate_path()?;
+
+        match std::fs::remove_dir_all(self.request.path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Validates the request path before it is created or removed, since the path is created and
+    /// later recursively removed it must not be able to target a directory outside of the allowed
+    /// root. The path must not be empty, must not contain parent directory components, must not
+    /// contain the current directory and must resolve to a directory within (and not equal to) the
+    /// allowed root. When no allowed root has been set the path must be within the temp directory
+    /// under the current directory or the system temp directory, never any other directory of the
+    /// current directory (e.g the source of the project).
+    fn validate_path(&self) -> Result<(), SandboxError> {
+        let path = self.request.path;
+        let invalid = |reason: &str| Err(SandboxError::InvalidRequest(format!("path {} {}", path.display(), reason)));
+
+        if path.as_os_str().is_empty() {
+            return invalid("is empty");
+        }
+
+        if path.components().any(|component| component == Component::ParentDir) {
+            return invalid("contains a parent directory component");
+        }
+
+        let current_dir = env::current_dir()?;
+
+        let allowed_roots = match self.allowed_root {
+            Some(allowed_root) => vec![resolve_path(&current_dir.join(allowed_root))],
+            None => vec![resolve_path(&current_dir.join(DEFAULT_ALLOWED_ROOT)), resolve_path(&env::temp_dir())],
+        };
+
+        let resolved = resolve_path(&current_dir.join(path));
+
+        if resolve_path(&current_dir).starts_with(&resolved) {
+            return invalid("contains the current directory");
+        }
+
+        if allowed_roots.iter().any(|root| resolved.starts_with(root) && resolved != *root) {
+            Ok(())
+        } else {
+            invalid("is not within an allowed root")
+        }
+    }
+}
+
+/// Writes the given contents to the file at the given path through a buffered writer, flushing the
+/// writer and syncing the file to disk before returning. This ensures the container never reads a
+/// partially written file, even when the system is under heavy load.
+///
+/// # Arguments
+/// * `path` - The path of the file being written.
+/// * `contents` - The contents being written to the file.
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(contents)?;
+
+    let file = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    file.sync_all()
+}
+
+/// Resolves the given absolute path by canonicalizing the deepest part of the path which exists,
+/// the remainder of the path which does not yet exist is joined onto the canonicalized part.
+///
+/// # Arguments
+/// * `path` - The absolute path being resolved.
+fn resolve_path(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find_map(|ancestor| {
+            let canonical = std::fs::canonicalize(ancestor).ok()?;
+            let remainder = path.strip_prefix(ancestor).ok()?;
+
+            Some(canonical.join(remainder))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Guards a directory that has been created for the sandbox, removing the directory and all of its
+/// contents once dropped unless the guard has been disarmed. This ensures the directory is removed
+/// even when returning early or panicking.
+struct DirectoryGuard<'a> {
+    path: &'a Path,
+    armed: bool,
+}
+
+impl<'a> DirectoryGuard<'a> {
+    /// Creates a new armed guard for the directory at the given path.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the directory being guarded.
+    fn new(path: &'a Path) -> DirectoryGuard<'a> {
+        DirectoryGuard { path, armed: true }
+    }
+
+    /// Disarms the guard, the directory will no longer be removed once the guard is dropped.
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for DirectoryGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = std::fs::remove_dir_all(self.path);
+        }
+    }
+}
+
+/// Sends the line of each of the given steps to the standard input once the followed output
+/// contains the expected text of the step, recording the output and the input of each step into
+/// the transcript. Stops once every step has been sent, the execution has finished or the deadline
+/// has passed. Returning the number of bytes of the output that were recorded.
+///
+/// # Arguments
+/// * `steps` - The steps that drive the standard input of the executed code.
+/// * `stdin` - The pipe of the standard input of the executed code.
+/// * `stdout` - The follower of the standard output of the executed code.
+/// * `transcript` - The transcript the output and the input are recorded into.
+/// * `finished` - If the execution has finished.
+/// * `deadline` - The instant after which no more steps are sent.
+fn drive_steps(steps: &[InteractiveStep],
+               mut stdin: &File,
+               stdout: &mut OutputFollower,
+               transcript: &mut Vec<TranscriptEntry>,
+               finished: impl Fn() -> bool,
+               deadline: Instant) -> io::Result<usize> {
+    let mut consumed = 0;
+
+    for step in steps {
+        let index = loop {
+            let finished = finished();
+            stdout.file.read_to_end(&mut stdout.buffer)?;
+
+            if let Some(index) = find_bytes(&stdout.buffer, step.expect) {
+                break index;
+            }
+
+            if finished || Instant::now() >= deadline {
+                return Ok(consumed);
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        let output: Vec<u8> = stdout.buffer.drain(..index + step.expect.len()).collect();
+        consumed += output.len();
+
+        transcript.push(TranscriptEntry::Output(String::from_utf8_lossy(&output).into_owned()));
+        writeln!(stdin, "{}", step.send)?;
+        transcript.push(TranscriptEntry::Input(step.send.to_string()));
+    }
+
+    Ok(consumed)
+}
+
+/// Follows a output file that is being written by the container, reading any newly written content
+/// and splitting it into complete lines. The markers written by the script are not treated as
+/// output.
+struct OutputFollower {
+    file: File,
+    buffer: Vec<u8>,
+}
+
+impl OutputFollower {
+    /// Opens the given output file to be followed from the start of the file.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the output file being followed.
+    fn open(path: &Path) -> io::Result<OutputFollower> {
+        Ok(OutputFollower { file: File::open(path)?, buffer: Vec::new() })
+    }
+
+    /// Reads any content that has been written since the last read, passing each complete line to
+    /// the given callback. Incomplete lines are kept until the rest of the line is written.
+    ///
+    /// # Arguments
+    /// * `on_line` - The callback invoked with each complete line.
+    fn read_lines(&mut self, on_line: &mut dyn FnMut(&str)) -> io::Result<()> {
+        self.file.read_to_end(&mut self.buffer)?;
+
+        while let Some(index) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=index).collect();
+            OutputFollower::emit(&line[..index], on_line);
+        }
+
+        Ok(())
+    }
+
+    /// Passes any remaining incomplete line to the given callback, this is used once the container
+    /// has exited and no more content will be written.
+    ///
+    /// # Arguments
+    /// * `on_line` - The callback invoked with the remaining line.
+    fn finish(&mut self, on_line: &mut dyn FnMut(&str)) {
+        if !self.buffer.is_empty() {
+            OutputFollower::emit(&self.buffer, on_line);
+            self.buffer.clear();
+        }
+    }
+
+    fn emit(line: &[u8], on_line: &mut dyn FnMut(&str)) {
+        let line = String::from_utf8_lossy(line);
+
+        // The marker of the script can be written on the same line as the last of the output of the
+        // executed code (when it does not end in a new line), so only the marker is dropped.
+        match line.find(END_OF_OUTPUT_MARKER) {
+            Some(0) => {}
+            Some(index) => on_line(&line[..index]),
+            None => on_line(&line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::LocalBackend;
+    use crate::testing::{registered, MockBackend, SHELL};
+
+    /// Builds a request for the python compiler with the given path.
+    fn request(path: &Path) -> SandboxRequest<'_> {
+        SandboxRequest::builder()
+            .id("test")
+            .timeout(Duration::from_secs(5))
+            .path(path)
+            .source("print('hello')")
+            .compiler(COMPILERS[0])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn output_containing_the_old_compile_marker_is_not_a_compile_failure() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: compiler_for("cpp").unwrap(), ..request(&path) };
+
+        let backend = MockBackend::new().with_stdout("answer *-COMPILE::FAILED-* 42\n");
+        let response = Sandbox::new(&request).with_backend(&backend).run().unwrap();
+
+        assert_eq!(response.stage, ExecutionStage::Run);
+        assert_eq!(response.stdout, "answer *-COMPILE::FAILED-* 42\n");
+    }
+
+    #[test]
+    fn failed_compile_is_reported_at_the_compile_stage() {
+        static FAILING: LanguageCompiler = LanguageCompiler {
+            language: "failing",
+            compiler: "false",
+            interpreter: false,
+            compiled_output: Some("true"),
+            ..SHELL
+        };
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&FAILING), ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stage, ExecutionStage::Compile);
+        assert_eq!(response.exit_code, Some(1));
+    }
+
+    #[test]
+    fn exit_code_above_128_is_not_reported_as_a_signal() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&SHELL), source_code: "exit 130", ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!((response.exit_code, response.signal), (Some(130), None));
+        assert_eq!(response.term_reason, TermReason::Exited(130));
+    }
+
+    #[test]
+    fn signal_terminating_the_code_is_reported_as_the_signal() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&SHELL), source_code: "kill -TERM $$", ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!((response.exit_code, response.signal), (None, Some(15)));
+        assert_eq!(response.term_reason, TermReason::Signaled(15));
+    }
+
+    /// A backend which writes the given standard output and standard error output as is, as if
+    /// they were written by the script (including any end of output marker).
+    struct ScriptOutputBackend(&'static str, &'static str);
+
+    impl ExecutionBackend for ScriptOutputBackend {
+        fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, _timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+            std::fs::write(&prepared.stdout_file, self.0)?;
+            std::fs::write(&prepared.stderr_file, self.1)?;
+
+            let now = SystemTime::now();
+
+            let container_exit = ContainerExit {
+                status_code: Some(0),
+                duration: Duration::default(),
+                started_at: now,
+                finished_at: now,
+                timed_out: false,
+                cancelled: false,
+                logs: None,
+                oom_killed: None,
+                compile_cache_hit: false,
+            };
+
+            Ok(SandboxResponse::from_request(request, prepared, container_exit)?)
+        }
+    }
+
+    #[test]
+    fn process_limit_is_read_from_the_usage_of_the_script() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = request(&path);
+
+        let backend = ScriptOutputBackend("out\n*-COMPILE::EOF-* 0.01 1024 5000 3\n", "");
+        let response = Sandbox::new(&request).with_backend(backend).run().unwrap();
+
+        assert!(response.process_limit_reached);
+        assert_eq!(response.stdout, "out\n");
+        assert_eq!(response.max_rss_bytes, Some(1024));
+        assert_eq!(response.cpu_time, Some(Duration::from_nanos(5000)));
+    }
+
+    #[test]
+    fn process_limit_is_not_detected_from_the_error_output() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = request(&path);
+
+        let backend = ScriptOutputBackend("*-COMPILE::EOF-* 0.01 - - 0\n", "fork: Resource temporarily unavailable\n");
+        let response = Sandbox::new(&request).with_backend(backend).run().unwrap();
+
+        assert!(!response.process_limit_reached);
+    }
+
+    #[test]
+    fn java_source_is_written_to_the_file_of_its_public_class() {
+        let java = compiler_for("java").unwrap();
+        assert_eq!(java.source_file_name(), "Main.java");
+        assert_eq!(compiler_for("cpp").unwrap().source_file_name(), "cpp.cpp");
+    }
+
+    #[test]
+    fn validate_path_rejects_parent_directory_traversal() {
+        let request = request(Path::new("temp/../src"));
+        assert!(matches!(Sandbox::new(&request).validate_path(), Err(SandboxError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn validate_path_rejects_current_directory_and_its_subdirectories() {
+        for path in [".", "src", "dockerFiles", "temp"] {
+            let request = request(Path::new(path));
+            assert!(Sandbox::new(&request).validate_path().is_err(), "{} was allowed", path);
+        }
+    }
+
+    #[test]
+    fn validate_path_accepts_temp_directories() {
+        let temp_path = SandboxRequest::temp_path("test").unwrap();
+
+        for path in [Path::new("temp/valid"), temp_path.as_path()] {
+            let request = request(path);
+            assert!(Sandbox::new(&request).validate_path().is_ok(), "{} was rejected", path.display());
+        }
+    }
+
+    #[test]
+    fn temp_path_is_unique_for_the_same_id() {
+        let paths: std::collections::HashSet<PathBuf> = (0..100).map(|_| SandboxRequest::temp_path("test").unwrap()).collect();
+        assert_eq!(paths.len(), 100);
+    }
+
+    #[test]
+    fn run_never_removes_a_directory_of_the_project() {
+        let request = request(Path::new("src"));
+        let result = Sandbox::new(&request).with_backend(MockBackend::new()).run();
+
+        assert!(matches!(result, Err(SandboxError::InvalidRequest(_))));
+        assert!(Path::new("src/sandbox.rs").is_file());
+    }
+
+    #[test]
+    fn prepare_returns_the_error_of_a_missing_script() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = request(&path);
+
+        let mut sandbox = Sandbox::new(&request).with_script_path(Path::new("dockerFiles/missing.sh"));
+        let error = sandbox.prepare().unwrap_err();
+        sandbox.cleanup().unwrap();
+
+        assert!(matches!(&error, SandboxError::Io(io_error) if io_error.kind() == io::ErrorKind::NotFound));
+        assert!(error.to_string().starts_with("sandbox io error: "));
+
+        let source = std::error::Error::source(&error).expect("the io error is the source of the error");
+        assert_eq!(source.downcast_ref::<io::Error>().map(io::Error::kind), Some(io::ErrorKind::NotFound));
+        assert!(std::error::Error::source(&SandboxError::Timeout).is_none());
+    }
+
+    #[test]
+    fn prepare_returns_the_error_of_a_path_that_cannot_be_created() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        std::fs::write(&path, "not a directory").unwrap();
+
+        let request = request(&path);
+        let result = Sandbox::new(&request).prepare();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(SandboxError::Io(_))));
+    }
+
+    #[test]
+    fn prepare_writes_the_source_code_as_is() {
+        let source = "def main():\n    print('hello')\n\nmain()\n";
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { source_code: source, ..request(&path) };
+
+        let mut sandbox = Sandbox::new(&request);
+        let paths = sandbox.prepare().unwrap();
+        let written = std::fs::read_to_string(&paths.source_file).unwrap();
+        sandbox.cleanup().unwrap();
+
+        assert_eq!(written, source);
+    }
+
+    #[test]
+    fn code_running_past_the_timeout_is_killed() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&SHELL), source_code: "sleep 5", timeout: Duration::from_millis(200), ..request(&path) };
+
+        let started = Instant::now();
+        let result = Sandbox::new(&request).with_backend(LocalBackend::new()).run();
+
+        assert!(matches!(result, Err(SandboxError::Timeout)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn exit_code_of_the_code_is_on_the_response() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = request(&path);
+
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_exit_code(3)).run().unwrap();
+        assert_eq!(response.exit_code, Some(3));
+
+        let response = Sandbox::new(&request).with_backend(MockBackend::new()).run().unwrap();
+        assert_eq!(response.exit_code, Some(0));
+    }
+
+    #[test]
+    fn stdin_data_of_each_test_is_directed_into_the_code() {
+        let first = vec!["1", "2"];
+        let second = vec!["3"];
+        let tests = [
+            SandboxTest { stdin_data: Some(&first), ..SandboxTest::new("first") },
+            SandboxTest { stdin_data: Some(&second), ..SandboxTest::new("second") },
+            SandboxTest::new("none"),
+        ];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, ..request(&path) };
+
+        let backend = MockBackend::new();
+        Sandbox::new(&request).with_backend(&backend).run().unwrap();
+
+        let stdin: Vec<String> = backend.executions().into_iter().map(|execution| execution.stdin).collect();
+        assert_eq!(stdin, ["1\n2\n", "3\n", ""]);
+    }
+
+    #[test]
+    fn stdin_data_is_read_by_the_code() {
+        let stdin_data = vec!["hello"];
+        let tests = [SandboxTest { stdin_data: Some(&stdin_data), ..SandboxTest::new("stdin") }];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&SHELL), source_code: "read line; echo \"got $line\"", tests: &tests, ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stdout, "got hello\n");
+    }
+
+    #[test]
+    fn output_is_compared_against_the_expected_output_of_each_test() {
+        let hello = vec!["hello"];
+        let bye = vec!["bye"];
+        let tests = [
+            SandboxTest { expected_stdout_data: Some(&hello), ..SandboxTest::new("hello") },
+            SandboxTest { expected_stdout_data: Some(&bye), ..SandboxTest::new("bye") },
+            SandboxTest::new("nothing"),
+        ];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_stdout("hello\n")).run().unwrap();
+
+        let results: Vec<SandboxTestResult> = response.test_results.iter().map(|test| test.result).collect();
+        assert_eq!(results, [SandboxTestResult::Passed, SandboxTestResult::Failed, SandboxTestResult::NotRan]);
+
+        let ids: Vec<&str> = response.test_results.iter().map(|test| test.id.as_str()).collect();
+        assert_eq!(ids, ["hello", "bye", "nothing"]);
+    }
+
+    #[test]
+    fn compiler_for_finds_the_compiler_regardless_of_case() {
+        for language in ["python", "Python", "PYTHON"] {
+            assert_eq!(compiler_for(language).map(|compiler| compiler.compiler), Some("python3"));
+        }
+
+        assert_eq!(compiler_for("javascript").map(|compiler| compiler.language), Some("Javascript"));
+        assert!(compiler_for("cobol").is_none());
+    }
+
+    /// The shell as a compiler, which checks the syntax of the source code when compiling and then
+    /// executes the source code as the compiled output.
+    const CHECKED_SHELL: LanguageCompiler = LanguageCompiler {
+        language: "checked",
+        interpreter: false,
+        additional_arguments: Some("-n"),
+        compiled_output: Some("sh checked.sh"),
+        ..SHELL
+    };
+
+    #[test]
+    fn compiled_output_is_executed_once_compiled() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&CHECKED_SHELL), source_code: "echo compiled", ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stage, ExecutionStage::Run);
+        assert_eq!(response.stdout, "compiled\n");
+    }
+
+    #[test]
+    fn failed_compile_marks_the_remaining_tests_as_not_ran() {
+        let tests = ["first", "second", "third"].map(|id| SandboxTest { expected_exit_code: Some(0), ..SandboxTest::new(id) });
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&CHECKED_SHELL), source_code: "echo compiled; fi", tests: &tests, ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stage, ExecutionStage::Compile);
+        assert_eq!(response.stdout, "");
+        assert!(!response.stderr.is_empty());
+
+        let results: Vec<SandboxTestResult> = response.test_results.iter().map(|test| test.result).collect();
+        assert_eq!(results, [SandboxTestResult::Failed, SandboxTestResult::NotRan, SandboxTestResult::NotRan]);
+    }
+
+    #[test]
+    fn failure_of_the_compiled_output_is_reported_at_the_run_stage() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&CHECKED_SHELL), source_code: "echo failing >&2; exit 3", ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stage, ExecutionStage::Run);
+        assert_eq!(response.exit_code, Some(3));
+        assert_eq!(response.stderr, "failing\n");
+    }
+
+    #[test]
+    fn source_file_is_named_with_the_extension_of_the_language() {
+        let expected = [("python", "python.py"), ("javascript", "Javascript.js"), ("cpp", "cpp.cpp"), ("go", "go.go"), ("rust", "rust.rs")];
+
+        for (language, source_file) in expected {
+            assert_eq!(compiler_for(language).unwrap().source_file_name(), source_file);
+        }
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let python = request(&path);
+
+        let mut sandbox = Sandbox::new(&python);
+        let paths = sandbox.prepare().unwrap();
+        sandbox.cleanup().unwrap();
+
+        assert_eq!(paths.source_file.file_name().and_then(|name| name.to_str()), Some("python.py"));
+    }
+
+    #[test]
+    fn streaming_passes_each_line_of_output_to_the_callbacks() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&SHELL), source_code: "echo one; echo error >&2; sleep 0.1; printf two", ..request(&path) };
+
+        let mut stdout_lines = Vec::new();
+        let mut stderr_lines = Vec::new();
+
+        let response = Sandbox::new(&request)
+            .with_backend(LocalBackend::new())
+            .run_streaming(|line| stdout_lines.push(line.to_string()), |line| stderr_lines.push(line.to_string()))
+            .unwrap();
+
+        assert_eq!(stdout_lines, ["one", "two"]);
+        assert_eq!(stderr_lines, ["error"]);
+        assert_eq!(response.stdout, "one\ntwo");
+    }
+
+    #[test]
+    fn output_follower_never_passes_the_end_of_output_marker() {
+        let mut lines = Vec::new();
+        let mut on_line = |line: &str| lines.push(line.to_string());
+
+        OutputFollower::emit(b"output", &mut on_line);
+        OutputFollower::emit(format!("last{} 0.01 - - -", END_OF_OUTPUT_MARKER).as_bytes(), &mut on_line);
+        OutputFollower::emit(format!("{} 0.01 - - -", END_OF_OUTPUT_MARKER).as_bytes(), &mut on_line);
+
+        assert_eq!(lines, ["output", "last"]);
+    }
+
+    #[test]
+    fn duration_of_the_execution_is_on_the_response() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&SHELL), source_code: "sleep 0.2", ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert!(response.duration >= Duration::from_millis(200), "{:?}", response.duration);
+        assert!(response.duration < request.timeout);
+    }
+
+    #[test]
+    fn read_output_truncates_output_beyond_the_max_bytes() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        std::fs::write(&path, "0123456789").unwrap();
+
+        let limited = read_output(&path, Some(4)).unwrap();
+        let exact = read_output(&path, Some(10)).unwrap();
+        let unlimited = read_output(&path, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(limited, (b"0123".to_vec(), true));
+        assert_eq!(exact, (b"0123456789".to_vec(), false));
+        assert_eq!(unlimited, (b"0123456789".to_vec(), false));
+    }
+
+    #[test]
+    fn decode_output_drops_a_character_cut_by_the_truncation() {
+        let output = "ab\u{e9}".as_bytes();
+
+        assert_eq!(decode_output(&output[..3], true), "ab");
+        assert_eq!(decode_output(output, false), "ab\u{e9}");
+    }
+
+    #[test]
+    fn output_beyond_the_max_output_bytes_is_truncated() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { max_output_bytes: Some(5), ..request(&path) };
+
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_stdout("hello world\n")).run().unwrap();
+        assert!(response.truncated);
+        assert_eq!(response.stdout, "hello");
+
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_stdout("hi\n").with_stderr("oops")).run().unwrap();
+        assert!(!response.truncated);
+    }
+
+    #[test]
+    fn failed_test_has_a_diff_of_the_output() {
+        let expected = vec!["1", "2", "3"];
+        let tests = [SandboxTest { expected_stdout_data: Some(&expected), ..SandboxTest::new("diff") }];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, ..request(&path) };
+
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_stdout("1\nx\n3\n")).run().unwrap();
+        assert_eq!(response.test_results[0].diff.as_deref(), Some(" 1\n-2\n+x\n 3\n"));
+
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_stdout("1\n2\n3\n")).run().unwrap();
+        assert_eq!(response.test_results[0].diff, None);
+    }
+
+    #[test]
+    fn script_is_copied_from_a_relative_or_absolute_path() {
+        let absolute = env::current_dir().unwrap().join(DEFAULT_SCRIPT_PATH);
+        let original = std::fs::read(&absolute).unwrap();
+
+        for script_path in [Path::new(DEFAULT_SCRIPT_PATH), absolute.as_path()] {
+            let path = SandboxRequest::temp_path("test").unwrap();
+            let request = request(&path);
+
+            let mut sandbox = Sandbox::new(&request).with_script_path(script_path);
+            let paths = sandbox.prepare().unwrap();
+            let copied = std::fs::read(&paths.script_file).unwrap();
+            sandbox.cleanup().unwrap();
+
+            assert_eq!(copied, original, "{} was not copied", script_path.display());
+        }
+    }
+
+    #[test]
+    fn validate_path_only_accepts_paths_within_the_allowed_root() {
+        let root = SandboxRequest::temp_path("root").unwrap();
+        let within = root.join("request");
+        let outside = SandboxRequest::temp_path("outside").unwrap();
+
+        assert!(Sandbox::new(&request(&within)).with_allowed_root(&root).validate_path().is_ok());
+        assert!(Sandbox::new(&request(&root)).with_allowed_root(&root).validate_path().is_err());
+        assert!(Sandbox::new(&request(&outside)).with_allowed_root(&root).validate_path().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_path_rejects_a_symbolic_link_out_of_the_allowed_root() {
+        let root = SandboxRequest::temp_path("root").unwrap();
+        std::fs::create_dir_all(&root).unwrap();
+
+        let link = root.join("link");
+        std::os::unix::fs::symlink(env::current_dir().unwrap(), &link).unwrap();
+
+        let result = Sandbox::new(&request(&link.join("src"))).with_allowed_root(&root).validate_path();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(matches!(result, Err(SandboxError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn decode_output_replaces_invalid_utf8() {
+        assert_eq!(decode_output(b"ok \xff\xfe done", false), "ok \u{fffd}\u{fffd} done");
+        assert_eq!(decode_output(b"ok \xff", true), "ok \u{fffd}");
+    }
+
+    #[test]
+    fn invalid_utf8_output_does_not_fail_the_response() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { compiler: registered(&SHELL), source_code: "printf 'a\\377b'", ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stdout, "a\u{fffd}b");
+    }
+
+    #[test]
+    fn directory_guard_removes_the_directory_on_panic() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+
+        let result = std::panic::catch_unwind(|| {
+            std::fs::create_dir_all(&path).unwrap();
+            let _guard = DirectoryGuard::new(&path);
+            panic!("failed while executing");
+        });
+
+        assert!(result.is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn disarmed_directory_guard_keeps_the_directory() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        std::fs::create_dir_all(&path).unwrap();
+
+        DirectoryGuard::new(&path).disarm();
+        let exists = path.exists();
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert!(exists);
+    }
+
+    /// A backend which panics while executing the request.
+    struct PanickingBackend;
+
+    impl ExecutionBackend for PanickingBackend {
+        fn run(&self, _prepared: &PreparedPaths, _request: &SandboxRequest, _timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+            panic!("backend failed");
+        }
+    }
+
+    #[test]
+    fn request_path_is_removed_when_the_backend_panics() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = request(&path);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| Sandbox::new(&request).with_backend(PanickingBackend).run()));
+
+        assert!(result.is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn each_test_is_executed_with_its_own_timeout() {
+        let tests = [
+            SandboxTest { timeout: Some(Duration::from_millis(300)), ..SandboxTest::new("short") },
+            SandboxTest::new("default"),
+        ];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, ..request(&path) };
+
+        let backend = MockBackend::new();
+        Sandbox::new(&request).with_backend(&backend).run().unwrap();
+
+        let timeouts: Vec<Duration> = backend.executions().into_iter().map(|execution| execution.timeout).collect();
+        assert_eq!(timeouts, [Duration::from_millis(300), Duration::from_secs(5)]);
+    }
+
+    #[test]
+    fn test_past_its_timeout_only_fails_that_test() {
+        let stdin_data = vec!["slow"];
+        let expected = vec!["fast"];
+        let tests = [
+            SandboxTest { stdin_data: Some(&stdin_data), timeout: Some(Duration::from_millis(200)), ..SandboxTest::new("slow") },
+            SandboxTest { expected_stdout_data: Some(&expected), ..SandboxTest::new("fast") },
+        ];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let source = "read speed; if [ \"$speed\" = slow ]; then sleep 5; fi; echo fast";
+        let request = SandboxRequest { compiler: registered(&SHELL), source_code: source, tests: &tests, ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert!(response.test_results[0].timed_out);
+        assert_eq!(response.test_results[0].result, SandboxTestResult::Failed);
+        assert!(!response.test_results[1].timed_out);
+        assert_eq!(response.test_results[1].result, SandboxTestResult::Passed);
+    }
+
+    #[test]
+    fn artifacts_are_kept_when_requested() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let keeping = SandboxRequest { keep_artifacts: true, ..request(&path) };
+
+        let response = Sandbox::new(&keeping).with_backend(MockBackend::new().with_stdout("hello\n")).run().unwrap();
+        let artifacts_path = response.artifacts_path.clone().unwrap();
+
+        let source = std::fs::read_to_string(artifacts_path.join("python.py")).unwrap();
+        std::fs::remove_dir_all(&artifacts_path).unwrap();
+
+        assert_eq!(artifacts_path, std::fs::canonicalize(env::temp_dir()).unwrap().join(path.file_name().unwrap()));
+        assert_eq!(source, "print('hello')");
+
+        let response = Sandbox::new(&request(&path)).with_backend(MockBackend::new()).run().unwrap();
+        assert_eq!(response.artifacts_path, None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn prepare_returns_the_paths_of_the_prepared_files() {
+        let stdin_data = vec!["input"];
+        let tests = [SandboxTest { stdin_data: Some(&stdin_data), ..SandboxTest::new("stdin") }];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, ..request(&path) };
+
+        let mut sandbox = Sandbox::new(&request);
+        let dry_run = sandbox.dry_run().unwrap();
+        let paths = &dry_run.paths;
+
+        let files = [
+            &paths.source_file,
+            &paths.script_file,
+            &paths.stdin_file,
+            &paths.stdout_file,
+            &paths.stderr_file,
+            &paths.usage_file,
+            &paths.status_file,
+        ];
+        let all_exist = files.iter().all(|file| file.is_file() && file.parent() == Some(paths.directory.as_path()));
+        let stdin = std::fs::read_to_string(&paths.stdin_file).unwrap();
+        sandbox.cleanup().unwrap();
+
+        assert_eq!(paths.directory, std::fs::canonicalize(env::temp_dir()).unwrap().join(path.file_name().unwrap()));
+        assert!(all_exist);
+        assert_eq!(stdin, "input\n");
+    }
+
+    #[test]
+    fn term_reason_describes_how_the_code_ended() {
+        let tests = [SandboxTest::new("only")];
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, memory_limit: Some(1024 * 1024), ..request(&path) };
+
+        let term_reason = |backend: MockBackend| Sandbox::new(&request).with_backend(backend).run().unwrap().term_reason;
+
+        assert_eq!(term_reason(MockBackend::new().with_exit_code(3)), TermReason::Exited(3));
+        assert_eq!(term_reason(MockBackend::new().with_timed_out(true)), TermReason::TimedOut);
+        assert_eq!(term_reason(MockBackend::new().with_exit_code(137).with_oom_killed(true)), TermReason::OutOfMemory);
+    }
+
+    #[test]
+    fn cancelled_request_is_not_executed() {
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { cancellation: Some(&cancellation), ..request(&path) };
+
+        let backend = MockBackend::new();
+        let result = Sandbox::new(&request).with_backend(&backend).run();
+
+        assert!(matches!(result, Err(SandboxError::Cancelled)));
+        assert!(backend.executions().is_empty());
+        assert!(!path.exists());
+    }
+
+    /// A backend which records the source file as it was before each execution, and then appends
+    /// to it so that any rewrite of the source file between executions is seen.
+    #[derive(Default)]
+    struct SourceRecordingBackend(std::sync::Mutex<Vec<String>>);
+
+    impl ExecutionBackend for SourceRecordingBackend {
+        fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+            let source = std::fs::read_to_string(&prepared.source_file)?;
+            std::fs::write(&prepared.source_file, format!("{}#", source))?;
+            self.0.lock().unwrap().push(source);
+
+            MockBackend::new().run(prepared, request, timeout)
+        }
+    }
+
+    #[test]
+    fn source_file_is_written_once_for_every_test() {
+        let tests = [SandboxTest::new("first"), SandboxTest::new("second"), SandboxTest::new("third")];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, ..request(&path) };
+
+        let backend = SourceRecordingBackend::default();
+        Sandbox::new(&request).with_backend(&backend).run().unwrap();
+
+        assert_eq!(*backend.0.lock().unwrap(), ["print('hello')", "print('hello')#", "print('hello')##"]);
+    }
+
+    #[test]
+    fn fail_fast_stops_after_the_first_failed_test() {
+        let hello = vec!["hello"];
+        let bye = vec!["bye"];
+        let tests = [
+            SandboxTest { expected_stdout_data: Some(&hello), ..SandboxTest::new("passing") },
+            SandboxTest { expected_stdout_data: Some(&bye), ..SandboxTest::new("failing") },
+            SandboxTest { expected_stdout_data: Some(&hello), ..SandboxTest::new("skipped") },
+        ];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let failing_fast = SandboxRequest { tests: &tests, fail_fast: true, ..request(&path) };
+
+        let backend = MockBackend::new().with_stdout("hello\n");
+        let response = Sandbox::new(&failing_fast).with_backend(&backend).run().unwrap();
+
+        let results: Vec<SandboxTestResult> = response.test_results.iter().map(|test| test.result).collect();
+        assert_eq!(results, [SandboxTestResult::Passed, SandboxTestResult::Failed, SandboxTestResult::NotRan]);
+        assert_eq!(backend.executions().len(), 2);
+
+        let backend = MockBackend::new().with_stdout("hello\n");
+        let response = Sandbox::new(&SandboxRequest { fail_fast: false, ..failing_fast }).with_backend(&backend).run().unwrap();
+
+        assert_eq!(response.test_results[2].result, SandboxTestResult::Passed);
+        assert_eq!(backend.executions().len(), 3);
+    }
+
+    #[test]
+    fn failed_test_reports_the_first_mismatching_line() {
+        let expected = vec!["1", "2", "3"];
+        let tests = [
+            SandboxTest { expected_stdout_data: Some(&expected), ..SandboxTest::new("output") },
+            SandboxTest { expected_stdout_data: Some(&expected), expected_exit_code: Some(1), ..SandboxTest::new("exit code") },
+        ];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, ..request(&path) };
+
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_stdout("1\n2\n4\n")).run().unwrap();
+        assert_eq!(response.test_results[0].first_mismatch_line, Some(2));
+
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_stdout("1\n2\n3\n")).run().unwrap();
+        assert_eq!(response.test_results[0].first_mismatch_line, None);
+        assert_eq!(response.test_results[1].result, SandboxTestResult::Failed);
+        assert_eq!((response.test_results[1].first_mismatch_line, response.test_results[1].diff.as_deref()), (None, None));
+    }
+
+    #[test]
+    fn expected_exit_code_must_match_the_exit_code() {
+        let expected = vec!["usage"];
+        let tests = [
+            SandboxTest { expected_exit_code: Some(2), ..SandboxTest::new("exit code") },
+            SandboxTest { expected_exit_code: Some(0), ..SandboxTest::new("success") },
+            SandboxTest { expected_stdout_data: Some(&expected), expected_exit_code: Some(2), ..SandboxTest::new("both") },
+        ];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let request = SandboxRequest { tests: &tests, ..request(&path) };
+        let response = Sandbox::new(&request).with_backend(MockBackend::new().with_stdout("usage\n").with_exit_code(2)).run().unwrap();
+
+        let results: Vec<SandboxTestResult> = response.test_results.iter().map(|test| test.result).collect();
+        assert_eq!(results, [SandboxTestResult::Passed, SandboxTestResult::Failed, SandboxTestResult::Passed]);
+    }
+
+    #[test]
+    fn stdout_bytes_are_kept_as_written_by_the_code() {
+        let tests = [
+            SandboxTest { expected_stdout_bytes: Some(b"a\xffb"), ..SandboxTest::new("bytes") },
+            SandboxTest { expected_stdout_bytes: Some(b"a\xfeb"), ..SandboxTest::new("other bytes") },
+        ];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let shell = SandboxRequest { compiler: registered(&SHELL), source_code: "printf 'a\\377b'", tests: &tests, ..request(&path) };
+        let response = Sandbox::new(&shell).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stdout_bytes, b"a\xffb");
+        assert_eq!(response.stdout, "a\u{fffd}b");
+        assert_eq!(response.test_results[0].result, SandboxTestResult::Passed);
+        assert_eq!(response.test_results[1].result, SandboxTestResult::Failed);
+    }
+
+    #[test]
+    fn strip_ansi_removes_the_escape_sequences() {
+        assert_eq!(strip_ansi("\u{1b}[1;31merror\u{1b}[0m: failed"), "error: failed");
+        assert_eq!(strip_ansi("\u{1b}]0;title\u{7}output"), "output");
+        assert_eq!(strip_ansi("\u{1b}]0;title\u{1b}\\output"), "output");
+        assert_eq!(strip_ansi("plain output"), "plain output");
+    }
+
+    #[test]
+    fn strip_ansi_request_keeps_the_stdout_bytes() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let source = "printf '\\033[32mok\\033[0m'";
+        let shell = SandboxRequest { compiler: registered(&SHELL), source_code: source, strip_ansi: true, ..request(&path) };
+        let response = Sandbox::new(&shell).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stdout, "ok");
+        assert_eq!(response.stdout_bytes, b"\x1b[32mok\x1b[0m");
+    }
+
+    #[test]
+    fn crlf_output_passes_the_test_and_keeps_the_stdout_bytes() {
+        let expected = vec!["hello", "world"];
+        let tests = [SandboxTest { expected_stdout_data: Some(&expected), ..SandboxTest::new("crlf") }];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let source = "printf 'hello\\r\\nworld\\r\\n'";
+        let shell = SandboxRequest { compiler: registered(&SHELL), source_code: source, tests: &tests, ..request(&path) };
+        let response = Sandbox::new(&shell).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.test_results[0].result, SandboxTestResult::Passed);
+        assert_eq!(response.stdout_bytes, b"hello\r\nworld\r\n");
+    }
+
+    #[test]
+    fn merge_output_interleaves_the_standard_error_output_into_the_stdout() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let source = "echo first; echo second >&2; echo third";
+        let shell = SandboxRequest { compiler: registered(&SHELL), source_code: source, merge_output: true, ..request(&path) };
+        let response = Sandbox::new(&shell).with_backend(LocalBackend::new()).run().unwrap();
+
+        assert_eq!(response.stdout, "first\nsecond\nthird\n");
+        assert_eq!(response.stderr, "");
+    }
+
+    #[test]
+    fn started_at_and_finished_at_bound_the_execution() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let shell = SandboxRequest { compiler: registered(&SHELL), source_code: "sleep 0.1", ..request(&path) };
+
+        let before = SystemTime::now();
+        let response = Sandbox::new(&shell).with_backend(LocalBackend::new()).run().unwrap();
+        let after = SystemTime::now();
+
+        assert!(before <= response.started_at && response.started_at <= response.finished_at);
+        assert!(response.finished_at <= after);
+        assert!(response.finished_at.duration_since(response.started_at).unwrap() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_results_display_as_friendly_strings() {
+        assert_eq!(SandboxTestResult::NotRan.to_string(), "not run");
+        assert_eq!(SandboxTestResult::Failed.to_string(), "failed");
+        assert_eq!(SandboxTestResult::Passed.to_string(), "passed");
+    }
+
+    #[test]
+    fn sanitize_id_replaces_the_unsafe_characters() {
+        assert_eq!(sanitize_id("test-1.2_3").unwrap(), "test-1.2_3");
+        assert_eq!(sanitize_id("../x y").unwrap(), ".._x_y");
+        assert_eq!(sanitize_id("a/b\\c;rm -rf").unwrap(), "a_b_c_rm_-rf");
+        assert!(matches!(sanitize_id("../.."), Err(SandboxError::InvalidRequest(_))));
+        assert!(matches!(sanitize_id(""), Err(SandboxError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn malicious_id_is_kept_within_the_temp_directory() {
+        let path = SandboxRequest::temp_path("../../etc/passwd").unwrap();
+
+        assert_eq!(path.parent(), Some(env::temp_dir().as_path()));
+        assert!(path.file_name().unwrap().to_str().unwrap().starts_with("compileme_.._.._etc_passwd_"));
+        assert!(SandboxRequest { id: "../..", ..request(&path) }.validate().is_err());
+    }
+
+    #[test]
+    fn prepare_writes_the_whole_of_a_large_source_before_executing() {
+        let source = "print('hello')\n".repeat(64 * 1024);
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let large = SandboxRequest { source_code: &source, ..request(&path) };
+
+        let mut sandbox = Sandbox::new(&large);
+        let paths = sandbox.prepare().unwrap();
+        let written = std::fs::read(&paths.source_file).unwrap();
+        sandbox.cleanup().unwrap();
+
+        assert_eq!(written.len(), source.len());
+        assert_eq!(written, source.as_bytes());
+    }
+
+    #[test]
+    fn write_synced_writes_more_than_the_buffer_of_the_writer() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let contents = vec![b'x'; 256 * 1024];
+
+        write_synced(&path, &contents).unwrap();
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(written, contents);
+    }
+
+    /// A backend which fails to execute the request.
+    struct FailingBackend;
+
+    impl ExecutionBackend for FailingBackend {
+        fn run(&self, _prepared: &PreparedPaths, _request: &SandboxRequest, _timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+            Err(SandboxError::ContainerFailed(125))
+        }
+    }
+
+    #[test]
+    fn artifacts_path_is_returned_when_the_backend_fails_with_kept_artifacts() {
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let keeping = SandboxRequest { keep_artifacts: true, ..request(&path) };
+
+        let result = Sandbox::new(&keeping).with_backend(FailingBackend).run();
+        let (artifacts_path, error) = match result {
+            Err(SandboxError::ArtifactsKept(artifacts_path, error)) => (artifacts_path, error),
+            result => panic!("expected the artifacts to be kept, got {:?}", result),
+        };
+
+        let source = std::fs::read_to_string(artifacts_path.join("python.py")).unwrap();
+        std::fs::remove_dir_all(&artifacts_path).unwrap();
+
+        assert_eq!(artifacts_path, std::fs::canonicalize(env::temp_dir()).unwrap().join(path.file_name().unwrap()));
+        assert_eq!(source, "print('hello')");
+        assert!(matches!(*error, SandboxError::ContainerFailed(125)));
+
+        let result = Sandbox::new(&request(&path)).with_backend(FailingBackend).run();
+        assert!(matches!(result, Err(SandboxError::ContainerFailed(125))));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn errors_display_their_details() {
+        assert_eq!(SandboxError::ContainerFailed(125).to_string(), "sandbox container failed with exit code 125");
+        assert_eq!(SandboxError::InvalidRequest("id is empty".into()).to_string(), "invalid sandbox request: id is empty");
+        assert_eq!(SandboxError::ImageNotFound("python".into()).to_string(), "sandbox image python was not found");
+        assert_eq!(SandboxError::Cancelled.to_string(), "sandbox request was cancelled");
+
+        let kept = SandboxError::ArtifactsKept(PathBuf::from("/tmp/test"), Box::new(SandboxError::Timeout));
+        assert_eq!(kept.to_string(), "sandbox container exceeded the timeout (artifacts kept at /tmp/test)");
+        assert!(std::error::Error::source(&kept).unwrap().downcast_ref::<SandboxError>().is_some());
+    }
+
+    /// The reason the given request is invalid, panicking if the request is valid.
+    fn invalid_reason(request: &SandboxRequest) -> String {
+        match request.validate() {
+            Err(SandboxError::InvalidRequest(reason)) => reason,
+            result => panic!("expected an invalid request, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn request_for_a_registered_language_is_valid() {
+        assert!(request(Path::new("temp/test")).validate().is_ok());
+        assert!(SandboxRequest { compiler: registered(&SHELL), ..request(Path::new("temp/test")) }.validate().is_ok());
+    }
+
+    #[test]
+    fn empty_id_is_an_invalid_request() {
+        assert_eq!(invalid_reason(&SandboxRequest { id: "", ..request(Path::new("temp/test")) }), "id is empty");
+    }
+
+    #[test]
+    fn id_without_letters_or_digits_is_an_invalid_request() {
+        let invalid = SandboxRequest { id: "../..", ..request(Path::new("temp/test")) };
+        assert_eq!(invalid_reason(&invalid), "id \"../..\" does not contain any letters or digits");
+    }
+
+    #[test]
+    fn zero_timeout_is_an_invalid_request() {
+        let invalid = SandboxRequest { timeout: Duration::ZERO, ..request(Path::new("temp/test")) };
+        assert_eq!(invalid_reason(&invalid), "timeout is zero or was not provided");
+    }
+
+    #[test]
+    fn empty_source_code_is_an_invalid_request() {
+        let invalid = SandboxRequest { source_code: "", ..request(Path::new("temp/test")) };
+        assert_eq!(invalid_reason(&invalid), "source code is empty");
+    }
+
+    #[test]
+    fn source_code_larger_than_the_max_is_an_invalid_request() {
+        let invalid = SandboxRequest { max_source_bytes: Some(4), ..request(Path::new("temp/test")) };
+        assert_eq!(invalid_reason(&invalid), "source code is larger than 4 bytes");
+    }
+
+    #[test]
+    fn compiler_without_an_image_is_an_invalid_request() {
+        let imageless = LanguageCompiler { virtual_machine_name: "", ..*COMPILERS[0] };
+        let invalid = SandboxRequest { compiler: &imageless, ..request(Path::new("temp/test")) };
+        assert_eq!(invalid_reason(&invalid), "compiler is missing a language, compiler or image");
+    }
+
+    #[test]
+    fn unknown_language_is_an_invalid_request_and_nothing_is_written() {
+        let cobol = LanguageCompiler { language: "cobol", ..SHELL };
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let invalid = SandboxRequest { compiler: &cobol, ..request(&path) };
+
+        assert_eq!(invalid_reason(&invalid), "unknown language cobol");
+        let result = Sandbox::new(&invalid).with_backend(MockBackend::new()).run();
+        assert!(matches!(result, Err(SandboxError::InvalidRequest(_))));
+        assert!(!path.exists());
+    }
+
+    /// A comparator which only accepts output equal to the expected output.
+    struct EqualComparator;
+
+    impl OutputComparator for EqualComparator {
+        fn compare(&self, expected: &str, actual: &str) -> bool {
+            expected == actual
+        }
+    }
+
+    #[test]
+    fn comparator_is_given_both_outputs_in_the_same_form() {
+        let expected = vec!["hello", "world"];
+        let tests = [SandboxTest {
+            expected_stdout_data: Some(&expected),
+            comparator: Some(&EqualComparator),
+            ..SandboxTest::new("equal")
+        }];
+
+        let path = SandboxRequest::temp_path("test").unwrap();
+        let equal = SandboxRequest { tests: &tests, ..request(&path) };
+
+        for stdout in ["hello\nworld\n", "hello\r\nworld\r\n", "hello\nworld"] {
+            let response = Sandbox::new(&equal).with_backend(MockBackend::new().with_stdout(stdout)).run().unwrap();
+            assert_eq!(response.test_results[0].result, SandboxTestResult::Passed, "{:?} was not accepted", stdout);
+        }
+
+        let response = Sandbox::new(&equal).with_backend(MockBackend::new().with_stdout("hello\nthere\n")).run().unwrap();
+        assert_eq!(response.test_results[0].result, SandboxTestResult::Failed);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::backend::ExecutionBackend;
+use crate::registry::register_compiler;
+use crate::sandbox::{ContainerExit, END_OF_OUTPUT_MARKER, LanguageCompiler, PreparedPaths, SandboxError, SandboxRequest, SandboxResponse};
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// The shell as a interpreter, which is installed on every host the local backend runs on. Other
+/// compilers executed locally are built from the shell with the struct update syntax.
+pub const SHELL: LanguageCompiler = LanguageCompiler {
+    language: "shell",
+    compiler: "sh",
+    source_extension: "sh",
+    source_file: None,
+    interpreter: true,
+    additional_arguments: None,
+    compiled_output: None,
+    virtual_machine_name: "shell_virtual_machine",
+    runtime_image: None,
+    env: &[],
+    standard_output_file: "shell.out",
+    standard_error_file: "shell.error.out",
+};
+
+/// A compiler which is not shipped with the crate, used when registering compilers at runtime.
+pub const DART: LanguageCompiler = LanguageCompiler {
+    language: "dart",
+    compiler: "dart",
+    source_extension: "dart",
+    source_file: None,
+    interpreter: true,
+    additional_arguments: None,
+    compiled_output: None,
+    virtual_machine_name: "dart_virtual_machine",
+    runtime_image: None,
+    env: &[],
+    standard_output_file: "dart.out",
+    standard_error_file: "dart.error.out",
+};
+
+/// Registers the given compiler within the default registry for its language, returning the
+/// compiler. Requests are only valid for compilers of registered languages, so this is used for the
+/// compilers (e.g the shell) that are not shipped with the crate.
+///
+/// # Arguments
+/// * `compiler` - The compiler being registered.
+pub fn registered(compiler: &'static LanguageCompiler<'static>) -> &'static LanguageCompiler<'static> {
+    register_compiler(compiler);
+    compiler
+}
+
+/// A backend that does not execute the code of the request, instead writing the configured output
+/// as if it was the output of the executed code. Every execution is recorded, allowing the
+/// comparison of the tests, the handling of timeouts and the assembly of the response to be
+/// checked without a container runtime.
+///
+/// The mock backend only exists for testing (both of the crate and of code using the crate), so it
+/// is only built for the tests of the crate or when the mock feature is enabled.
+///
+/// # Example
+/// ```
+/// # use compileme::testing::MockBackend;
+/// # use compileme::sandbox::{self, Sandbox, SandboxRequest, SandboxTest, SandboxTestResult};
+/// # use std::time::Duration;
+/// let expected_hello = vec!["hello"];
+/// let expected_bye = vec!["bye"];
+///
+/// let test = |id, expected| SandboxTest { expected_stdout_data: Some(expected), ..SandboxTest::new(id) };
+///
+/// let tests = [test("hello", &expected_hello), test("bye", &expected_bye)];
+/// let path = SandboxRequest::temp_path("mock").unwrap();
+///
+/// let request = SandboxRequest::builder()
+///     .id("mock")
+///     .timeout(Duration::from_secs(20))
+///     .path(&path)
+///     .source("print('hello')")
+///     .compiler(sandbox::compiler_for("python").unwrap())
+///     .tests(&tests)
+///     .build()
+///     .unwrap();
+///
+/// let backend = MockBackend::new().with_stdout("hello\n");
+/// let response = Sandbox::new(&request).with_backend(&backend).run().unwrap();
+///
+/// assert_eq!(response.test_results[0].result, SandboxTestResult::Passed);
+/// assert_eq!(response.test_results[1].result, SandboxTestResult::Failed);
+/// assert_eq!(backend.executions().len(), 2);
+///
+/// let backend = MockBackend::new().with_timed_out(true);
+/// let response = Sandbox::new(&request).with_backend(&backend).run().unwrap();
+///
+/// assert!(response.test_results.iter().all(|test| test.timed_out));
+/// ```
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+    timed_out: bool,
+    oom_killed: bool,
+    executions: Mutex<Vec<MockExecution>>,
+}
+
+/// The details of a single execution of a request by the mock backend.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MockExecution {
+    /// The id of the request that was executed.
+    pub id: String,
+    /// The standard input that was written for the execution.
+    pub stdin: String,
+    /// The timeout the execution was given.
+    pub timeout: Duration,
+}
+
+impl MockBackend {
+    /// Creates a new mock backend, by default every execution exits successfully without writing
+    /// any output.
+    pub fn new() -> MockBackend {
+        MockBackend::default()
+    }
+
+    /// Sets the standard output that is written by every execution.
+    ///
+    /// # Arguments
+    /// * `stdout` - The standard output of the execution.
+    pub fn with_stdout(mut self, stdout: &str) -> Self {
+        self.stdout = stdout.to_string();
+        self
+    }
+
+    /// Sets the standard error output that is written by every execution.
+    ///
+    /// # Arguments
+    /// * `stderr` - The standard error output of the execution.
+    pub fn with_stderr(mut self, stderr: &str) -> Self {
+        self.stderr = stderr.to_string();
+        self
+    }
+
+    /// Sets the exit code of every execution, by default this is zero.
+    ///
+    /// # Arguments
+    /// * `exit_code` - The exit code of the execution.
+    pub fn with_exit_code(mut self, exit_code: i32) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+
+    /// Sets if every execution is marked as timed out, by default they are not.
+    ///
+    /// # Arguments
+    /// * `timed_out` - If the execution timed out.
+    pub fn with_timed_out(mut self, timed_out: bool) -> Self {
+        self.timed_out = timed_out;
+        self
+    }
+
+    /// Sets if every execution is marked as killed for exceeding its memory limit, the same as a
+    /// container the runtime reports as oom killed. By default they are not.
+    ///
+    /// # Arguments
+    /// * `oom_killed` - If the execution was killed for exceeding its memory limit.
+    ///
+    /// # Example
+    /// ```
+    /// # use compileme::testing::MockBackend;
+    /// # use compileme::sandbox::{self, Sandbox, SandboxRequest, TermReason};
+    /// # use std::time::Duration;
+    /// let path = SandboxRequest::temp_path("oom").unwrap();
+    ///
+    /// let request = SandboxRequest::builder()
+    ///     .id("oom")
+    ///     .timeout(Duration::from_secs(20))
+    ///     .path(&path)
+    ///     .source("data = bytearray(64 * 1024 * 1024)")
+    ///     .compiler(sandbox::compiler_for("python").unwrap())
+    ///     .memory_limit(16 * 1024 * 1024)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let backend = MockBackend::new().with_exit_code(137).with_oom_killed(true);
+    /// let response = Sandbox::new(&request).with_backend(&backend).run().unwrap();
+    ///
+    /// assert!(response.out_of_memory);
+    /// assert_eq!(response.term_reason, TermReason::OutOfMemory);
+    /// ```
+    pub fn with_oom_killed(mut self, oom_killed: bool) -> Self {
+        self.oom_killed = oom_killed;
+        self
+    }
+
+    /// The executions of the mock backend so far, in the order they were executed.
+    pub fn executions(&self) -> Vec<MockExecution> {
+        self.executions.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+}
+
+impl ExecutionBackend for MockBackend {
+    fn run(&self, prepared: &PreparedPaths, request: &SandboxRequest, timeout: Duration) -> Result<SandboxResponse, SandboxError> {
+        self.executions.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(MockExecution {
+            id: request.id.to_string(),
+            stdin: fs::read_to_string(&prepared.stdin_file)?,
+            timeout,
+        });
+
+        // The outputs are written the same as the script executed within the container, including
+        // the end of output marker, so that the response is read back the same as any other.
+        let mut stdout = OpenOptions::new().append(true).open(&prepared.stdout_file)?;
+        stdout.write_all(self.stdout.as_bytes())?;
+
+        if request.merge_output {
+            stdout.write_all(self.stderr.as_bytes())?;
+        } else {
+            fs::write(&prepared.stderr_file, &self.stderr)?;
+        }
+
+        if !self.timed_out {
+            writeln!(stdout, "{} - - - -", END_OF_OUTPUT_MARKER)?;
+        }
+
+        let now = SystemTime::now();
+
+        let container_exit = ContainerExit {
+            status_code: if self.timed_out { None } else { Some(self.exit_code) },
+            duration: Duration::default(),
+            started_at: now,
+            finished_at: now,
+            timed_out: self.timed_out,
+            cancelled: false,
+            logs: None,
+            oom_killed: Some(self.oom_killed),
+            compile_cache_hit: false,
+        };
+
+        Ok(SandboxResponse::from_request(request, prepared, container_exit)?)
+    }
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+/// The resource usage of the executed code as measured by `/usr/bin/time -v` within the container,
+/// this is only measured when the request is measuring resources since it requires the binary
+/// within the image. Unlike the usage reported by the cgroup of the container, this only measures
+/// the executed code rather than everything executed within the container.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub struct ResourceUsage {
+    /// The wall clock time the executed code was running for.
+    pub elapsed: Duration,
+    /// The max resident set size of the executed code in bytes.
+    pub max_rss_bytes: u64,
+    /// The number of page faults of the executed code which required reading from disk.
+    pub major_page_faults: u64,
+    /// The number of page faults of the executed code which were resolved without reading from
+    /// disk (e.g reclaiming a frame).
+    pub minor_page_faults: u64,
+}
+
+impl ResourceUsage {
+    /// Parses the resource usage from the verbose output of `/usr/bin/time -v`, returning none if
+    /// any of the measurements are missing or could not be parsed. Any other lines of the output
+    /// are ignored.
+    ///
+    /// # Arguments
+    /// * `output` - The verbose output of the time binary.
+    ///
+    /// # Example
+    /// ```
+    /// # use compileme::usage::ResourceUsage;
+    /// # use std::time::Duration;
+    /// let output = "\tCommand being timed: \"python3 python.py -\"
+    /// \tUser time (seconds): 0.01
+    /// \tElapsed (wall clock) time (h:mm:ss or m:ss): 0:01.50
+    /// \tMaximum resident set size (kbytes): 9216
+    /// \tMajor (requiring I/O) page faults: 2
+    /// \tMinor (reclaiming a frame) page faults: 1093
+    /// \tExit status: 0";
+    ///
+    /// assert_eq!(ResourceUsage::parse(output), Some(ResourceUsage {
+    ///     elapsed: Duration::from_millis(1500),
+    ///     max_rss_bytes: 9216 * 1024,
+    ///     major_page_faults: 2,
+    ///     minor_page_faults: 1093,
+    /// }));
+    ///
+    /// assert_eq!(ResourceUsage::parse("Command terminated by signal 9"), None);
+    /// ```
+    pub fn parse(output: &str) -> Option<ResourceUsage> {
+        let mut elapsed = None;
+        let mut max_rss_bytes = None;
+        let mut major_page_faults = None;
+        let mut minor_page_faults = None;
+
+        for line in output.lines() {
+            // Each measurement is a description followed by the value, the description itself can
+            // contain a colon (e.g h:mm:ss) so the value follows the last colon and space.
+            let (description, value) = match line.trim().rsplit_once(": ") {
+                Some(measurement) => measurement,
+                None => continue,
+            };
+
+            match description {
+                "Elapsed (wall clock) time (h:mm:ss or m:ss)" => elapsed = parse_elapsed(value),
+                "Maximum resident set size (kbytes)" => max_rss_bytes = value.parse::<u64>().ok().map(|kbytes| kbytes * 1024),
+                "Major (requiring I/O) page faults" => major_page_faults = value.parse().ok(),
+                "Minor (reclaiming a frame) page faults" => minor_page_faults = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        Some(ResourceUsage {
+            elapsed: elapsed?,
+            max_rss_bytes: max_rss_bytes?,
+            major_page_faults: major_page_faults?,
+            minor_page_faults: minor_page_faults?,
+        })
+    }
+}
+
+/// Parses the elapsed wall clock time in the form of h:mm:ss or m:ss, where the seconds can be
+/// fractional (e.g 1:02:03 or 0:01.50).
+///
+/// # Arguments
+/// * `value` - The elapsed time being parsed.
+fn parse_elapsed(value: &str) -> Option<Duration> {
+    let mut parts = value.rsplit(':');
+    let seconds: f64 = parts.next()?.parse().ok()?;
+
+    let mut total = seconds;
+    let mut multiplier = 60.0;
+
+    for part in parts {
+        total += part.parse::<f64>().ok()? * multiplier;
+        multiplier *= 60.0;
+    }
+
+    if total.is_finite() && total >= 0.0 {
+        Some(Duration::from_secs_f64(total))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The verbose output of the time binary for a execution using 9 megabytes of memory.
+    const OUTPUT: &str = "\tCommand being timed: \"sh ./script.sh\"
+\tElapsed (wall clock) time (h:mm:ss or m:ss): 1:02:03.25
+\tMaximum resident set size (kbytes): 9216
+\tMajor (requiring I/O) page faults: 0
+\tMinor (reclaiming a frame) page faults: 512
+\tExit status: 0";
+
+    #[test]
+    fn parse_reads_every_measurement() {
+        assert_eq!(ResourceUsage::parse(OUTPUT), Some(ResourceUsage {
+            elapsed: Duration::from_millis(3_723_250),
+            max_rss_bytes: 9216 * 1024,
+            major_page_faults: 0,
+            minor_page_faults: 512,
+        }));
+    }
+
+    #[test]
+    fn parse_requires_every_measurement() {
+        let missing: String = OUTPUT.lines().filter(|line| !line.contains("Major")).collect::<Vec<&str>>().join("\n");
+        let invalid = OUTPUT.replace("9216", "lots");
+
+        assert_eq!(ResourceUsage::parse(&missing), None);
+        assert_eq!(ResourceUsage::parse(&invalid), None);
+        assert_eq!(ResourceUsage::parse(""), None);
+    }
+
+    #[test]
+    fn parse_elapsed_reads_hours_minutes_and_seconds() {
+        assert_eq!(parse_elapsed("0:01.50"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_elapsed("2:03"), Some(Duration::from_secs(123)));
+        assert_eq!(parse_elapsed("1:00:00"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_elapsed("a:00"), None);
+        assert_eq!(parse_elapsed("-1"), None);
+        assert_eq!(parse_elapsed(""), None);
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+use std::process::{self, Command, Output};
+use std::{env, fs};
+
+/// Runs the binary with the given arguments from the root of the crate, which is where the script
+/// executed within the request path is found.
+fn compileme(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_compileme"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap()
+}
+
+/// Writes the given contents to a file with the given name within the temp directory, unique to the
+/// current process so that concurrent runs of the tests do not share files.
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("compileme_cli_{}_{}", process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn missing_arguments_print_the_usage() {
+    let output = compileme(&[]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: compileme --language"));
+}
+
+#[test]
+fn list_languages_prints_the_built_in_compilers() {
+    let output = compileme(&["list-languages"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.lines().any(|line| line == "python\tpython_virtual_machine"));
+    assert!(stdout.lines().any(|line| line == "rust\trust_virtual_machine"));
+}
+
+#[test]
+fn unsupported_language_is_an_error() {
+    let source_file = temp_file("main.cob", "DISPLAY 'HELLO'.");
+    let output = compileme(&["--language", "cobol", "--source-file", source_file.to_str().unwrap()]);
+    fs::remove_file(&source_file).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "invalid sandbox request: unsupported language cobol\n");
+}
+
+#[test]
+fn local_run_prints_the_output_test_results_and_exit_code() {
+    let source = "const name = require('fs').readFileSync(0, 'utf8').trim();\nconsole.log(`hello ${name}`);\nprocess.exit(3);\n";
+    let source_file = temp_file("greet.js", source);
+    let stdin_file = temp_file("greet.in", "world\n");
+
+    let output = compileme(&[
+        "--language", "javascript",
+        "--source-file", source_file.to_str().unwrap(),
+        "--stdin-file", stdin_file.to_str().unwrap(),
+        "--local",
+    ]);
+
+    fs::remove_file(&source_file).unwrap();
+    fs::remove_file(&stdin_file).unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(3), "unexpected error output: {}", stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+    assert!(stderr.ends_with("test stdin: not run\nexited with code 3\n"), "unexpected error output: {}", stderr);
+}